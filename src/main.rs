@@ -1,43 +1,629 @@
 extern crate rand;
+#[cfg(feature = "render")]
 extern crate sdl2;
 
 use road_intersection_raid as defs;
 
+use clap::{Args, Parser, Subcommand};
+#[cfg(feature = "render")]
+use rand::Rng;
+#[cfg(feature = "render")]
 use sdl2::event::Event;
+#[cfg(feature = "render")]
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/*
+The command line of the binary. Without a subcommand it behaves like run with no options.
+
+run: the interactive simulation in an SDL window, which needs the render feature
+headless: simulates a run as fast as possible, or at --speed, and writes its outputs
+batch: runs every given scenario with every given seed, headless, and sums them up
+replay: plays back a session recorded with --record, in the window when it was built with the render feature
+*/
+#[derive(Parser)]
+#[command(about = "Road intersection traffic simulation")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(about = "Run the interactive simulation in a window")]
+    Run {
+        #[command(flatten)]
+        source: Source,
+        #[command(flatten)]
+        options: Options,
+    },
+    #[command(about = "Simulate without a window and write the outputs")]
+    Headless {
+        #[command(flatten)]
+        source: Source,
+        #[command(flatten)]
+        options: Options,
+    },
+    #[command(about = "Run several scenarios and seeds headless and summarize them")]
+    Batch {
+        #[arg(long = "scenario", help = "Scenario file to run, may be repeated")]
+        scenarios: Vec<PathBuf>,
+        #[arg(
+            long = "seed",
+            value_delimiter = ',',
+            help = "Seeds to run every scenario with, comma separated or repeated"
+        )]
+        seeds: Vec<u64>,
+        #[command(flatten)]
+        options: Options,
+    },
+    #[command(about = "Play back a session recorded with --record")]
+    Replay {
+        #[arg(help = "Recording written with --record")]
+        recording: PathBuf,
+        #[arg(long, help = "Simulated second to start the replay at")]
+        start: Option<f64>,
+        #[command(flatten)]
+        options: Options,
+    },
+}
+
+/*
+Where a run comes from: a scenario file and the seed, which overrides the scenario's.
+*/
+#[derive(Args)]
+struct Source {
+    #[arg(long, help = "Scenario file, TOML or JSON")]
+    scenario: Option<PathBuf>,
+    #[arg(long, help = "Seed of the run, random by default")]
+    seed: Option<u64>,
+}
+
+/*
+The options shared by every subcommand. duration, pedestrians, demand and turning override the scenario, and are ignored by a replay, which spawns what was recorded.
+*/
+#[derive(Args, Clone)]
+struct Options {
+    #[arg(long, help = "Directory to write the trips, lights and queues into")]
+    output: Option<PathBuf>,
+    #[arg(long, default_value = "csv", help = "Output format, csv or jsonl")]
+    format: defs::ExportFormat,
+    #[arg(long, help = "Write the outputs while the run goes on")]
+    stream: bool,
+    #[arg(long, help = "Record the session into a file for replay")]
+    record: Option<PathBuf>,
+    #[arg(long, help = "Directory to render PNG frames into, offscreen")]
+    frames: Option<PathBuf>,
+    #[arg(long, help = "Animated GIF to render the run into, offscreen")]
+    gif: Option<PathBuf>,
+    #[arg(
+        long,
+        default_value_t = 1,
+        help = "Render a frame every this many ticks for --frames and --gif"
+    )]
+    frame_every: u64,
+    #[arg(
+        long,
+        help = "Time scale, simulated seconds per real second from 0.25 to 100, 1 in the window and unlimited headless by default"
+    )]
+    speed: Option<f64>,
+    #[arg(long, help = "Simulated seconds to run")]
+    duration: Option<f64>,
+    #[arg(
+        long,
+        help = "Run the intersection with reservations instead of lights, the scenario's signals included"
+    )]
+    reservations: bool,
+    #[arg(long, help = "Pedestrians per hour on each crosswalk")]
+    pedestrians: Option<f64>,
+    #[arg(long, help = "Vehicles per hour on every approach, Poisson arrivals")]
+    demand: Option<f64>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Left, straight and right shares of every approach"
+    )]
+    turning: Option<Vec<f64>>,
+}
+
+/*
+A run in progress: the Road, the tick to stop at and the exporter writing its outputs into the output directory. A replay keeps the Recording it plays back so it can start it again when seeking back, and a recorded session the file to save its recording into.
+*/
+struct Session {
+    road: defs::Road,
+    duration: Option<u64>,
+    exporter: Option<defs::Exporter>,
+    output: Option<PathBuf>,
+    replay: Option<defs::Recording>,
+    record: Option<PathBuf>,
+    capture: Option<defs::Capture>,
+    stream: bool,
+    verbose: bool,
+    logged: usize,
+}
+
+/*
+Converts a number of simulated seconds given to the named option into ticks.
+*/
+fn ticks(option: &str, seconds: f64) -> Result<u64, String> {
+    if seconds >= 0.0 && seconds.is_finite() {
+        Ok((seconds * defs::TICKS_PER_SECOND as f64).round() as u64)
+    } else {
+        Err(format!("{option}: {seconds} is not a duration"))
+    }
+}
+
+impl Session {
+    /*
+    Builds the Road of a run from the scenario, or the defaults, then applies the seed and the overriding options and opens the outputs. --reservations and --pedestrians replace the scenario's controller and pedestrian demand before the Road is built, so that it is checked with them; reservations and pedestrians do not go together. With --record the recording starts here, after the seed is set.
+    */
+    fn new(
+        scenario: Option<&Path>,
+        seed: Option<u64>,
+        options: &Options,
+    ) -> Result<Session, String> {
+        let mut scenario = scenario.map(defs::Scenario::load).transpose()?;
+        if let Some(scenario) = scenario.as_mut() {
+            if options.reservations {
+                scenario.signals.controller = defs::ControllerKind::Reservations;
+            }
+            if let Some(pedestrians) = options.pedestrians {
+                scenario.layout.pedestrians = pedestrians;
+            }
+        }
+        let mut road = match &scenario {
+            Some(scenario) => scenario.road()?,
+            None if options.reservations => {
+                defs::Road::with_manager(defs::IntersectionManager::new())
+            }
+            None => defs::Road::new(),
+        };
+        if let Some(seed) = seed {
+            road.reseed(seed);
+        }
+        if let Some(pedestrians) = options.pedestrians {
+            road.pedestrian_demand = pedestrians;
+        }
+        if road.manager.is_some() && road.pedestrian_demand > 0.0 {
+            return Err(
+                "--pedestrians: pedestrians would never cross, reservations have no pedestrian phase"
+                    .to_string(),
+            );
+        }
+        if let Some(flow) = options.demand {
+            road.demand = defs::Demand::uniform(flow, defs::Headway::Poisson);
+            road.demand
+                .validate()
+                .map_err(|error| format!("--demand: {error}"))?;
+        }
+        if let Some(turning) = &options.turning {
+            let shares: [f64; 3] = turning
+                .as_slice()
+                .try_into()
+                .map_err(|_| "--turning: three shares are needed".to_string())?;
+            for side in defs::Side::ALL {
+                road.turning = road.turning.clone().with_side(side, shares);
+            }
+            road.turning
+                .validate()
+                .map_err(|error| format!("--turning: {error}"))?;
+        }
+        if options.record.is_some() {
+            let signals = match &scenario {
+                Some(scenario) => scenario.signals.clone(),
+                None if options.reservations => defs::SignalConfig {
+                    controller: defs::ControllerKind::Reservations,
+                    ..defs::SignalConfig::default()
+                },
+                None => defs::SignalConfig::default(),
+            };
+            road.recording = Some(defs::Recording::new(road.seed, signals, road.geometry));
+        }
+        let duration = match options.duration {
+            Some(duration) => Some(ticks("--duration", duration)?),
+            None => scenario.as_ref().and_then(defs::Scenario::duration_ticks),
+        };
+        Session::open(road, duration, None, options)
+    }
+
+    /*
+    Builds a session that plays back a recording from the given second, or from the start. It ends where the recorded session ended when it has no duration.
+    */
+    fn replay(path: &Path, start: Option<f64>, options: &Options) -> Result<Session, String> {
+        if options.record.is_some() {
+            return Err(
+                "--record: a replay plays back a recording and cannot record one".to_string(),
+            );
+        }
+        let recording = defs::Recording::load(path)?;
+        let duration = match options.duration {
+            Some(duration) => ticks("--duration", duration)?,
+            None => recording.end(),
+        };
+        let road = recording.road()?;
+        let mut session = Session::open(road, Some(duration), Some(recording), options)?;
+        if let Some(start) = start {
+            session.seek(ticks("--start", start)?)?;
+        }
+        Ok(session)
+    }
+
+    /*
+    Checks the options shared by every run and opens the outputs.
+    */
+    fn open(
+        road: defs::Road,
+        duration: Option<u64>,
+        replay: Option<defs::Recording>,
+        options: &Options,
+    ) -> Result<Session, String> {
+        if let Some(speed) = options.speed
+            && !(defs::MIN_TIME_SCALE..=defs::MAX_TIME_SCALE).contains(&speed)
+        {
+            return Err(format!(
+                "--speed: {speed} is not a time scale from {} to {}",
+                defs::MIN_TIME_SCALE,
+                defs::MAX_TIME_SCALE
+            ));
+        }
+        let exporter = options
+            .output
+            .as_ref()
+            .map(|dir| {
+                defs::Exporter::create(dir, options.format)
+                    .map_err(|error| format!("{}: {error}", dir.display()))
+            })
+            .transpose()?;
+        if options.frame_every == 0 {
+            return Err("--frame-every: 0 is not a number of ticks".to_string());
+        }
+        let capture = if options.frames.is_some() || options.gif.is_some() {
+            Some(defs::Capture::new(
+                options.frame_every,
+                (road.geometry.width, road.geometry.height),
+                options.frames.as_deref(),
+                options.gif.as_deref(),
+            )?)
+        } else {
+            None
+        };
+        Ok(Session {
+            road,
+            duration,
+            exporter,
+            output: options.output.clone(),
+            replay,
+            record: options.record.clone(),
+            capture,
+            stream: options.stream,
+            verbose: true,
+            logged: 0,
+        })
+    }
+
+    /*
+    Brings a replay to the given tick. Going forward steps through the ticks in between, going back starts the replay again, with new outputs, and steps from the start.
+    */
+    fn seek(&mut self, tick: u64) -> Result<(), String> {
+        let Some(recording) = &self.replay else {
+            return Ok(());
+        };
+        let tick = self.duration.map_or(tick, |duration| tick.min(duration));
+        if tick < self.road.tick {
+            self.road = recording.road()?;
+            self.logged = 0;
+            if let (Some(exporter), Some(dir)) = (&self.exporter, &self.output) {
+                self.exporter = Some(
+                    defs::Exporter::create(dir, exporter.format)
+                        .map_err(|error| format!("{}: {error}", dir.display()))?,
+                );
+            }
+        }
+        while self.road.tick < tick {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    fn is_over(&self) -> bool {
+        self.duration
+            .is_some_and(|duration| self.road.tick >= duration)
+    }
+
+    /*
+    Simulates one tick: runs Road::simulation_loop, prints the new emergency vehicle preemption events, streams the outputs and renders the frame offscreen when one is due.
+    */
+    fn step(&mut self) -> Result<(), String> {
+        self.road.simulation_loop();
+        if let Some(capture) = self.capture.as_mut() {
+            capture.capture(&self.road)?;
+        }
+        if self.verbose {
+            for event in &self.road.preemption.log[self.logged..] {
+                println!(
+                    "tick {}: preemption {:?} for emergency vehicle {} from {:?}, queues {:?}",
+                    event.tick, event.stage, event.car, event.side, event.queues
+                );
+            }
+        }
+        self.logged = self.road.preemption.log.len();
+        if self.stream
+            && let Some(exporter) = self.exporter.as_mut()
+        {
+            exporter
+                .write_new(&self.road)
+                .map_err(|error| error.to_string())?;
+        }
+        Ok(())
+    }
+
+    /*
+    Simulates until the duration is reached, at the given speed or as fast as possible.
+    */
+    fn run_headless(&mut self, speed: Option<f64>) -> Result<(), String> {
+        if self.duration.is_none() {
+            return Err(
+                "a headless run needs --duration or a scenario with a duration".to_string(),
+            );
+        }
+        let start = Instant::now();
+        while !self.is_over() {
+            self.step()?;
+            if let Some(speed) = speed {
+                let due = Duration::from_secs_f64(self.road.time() / speed);
+                if let Some(ahead) = due.checked_sub(start.elapsed()) {
+                    std::thread::sleep(ahead);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    Finishes the outputs and the GIF, saves the recording and returns the statistics of the run. A replay that did not change the lights the way they were recorded is reported, with the tick it diverged at.
+    */
+    fn finish(&mut self) -> Result<defs::Stats, String> {
+        if let Some(exporter) = self.exporter.as_mut() {
+            exporter
+                .finish(&self.road)
+                .map_err(|error| error.to_string())?;
+        }
+        if let Some(capture) = self.capture.as_mut() {
+            capture.finish()?;
+            println!("rendered {} frames", capture.captured);
+        }
+        if let (Some(recording), Some(path)) = (self.road.recording.as_mut(), &self.record) {
+            recording.header.end = Some(self.road.tick);
+            recording.save(path)?;
+            println!(
+                "recorded {} events into {}",
+                recording.records.len(),
+                path.display()
+            );
+        }
+        if let Some(tick) = self
+            .road
+            .playback
+            .as_ref()
+            .and_then(|playback| playback.diverged)
+        {
+            eprintln!("warning: the replay diverged from the recording at tick {tick}");
+        }
+        Ok(self.road.stats())
+    }
+}
+
+fn print_stats(seed: u64, stats: &defs::Stats) {
+    let seconds = defs::TICKS_PER_SECOND as f64;
+    println!(
+        "seed {}, vehicles: {} spawned, {} through, delay mean {:.1}s p95 {:.1}s max {:.1}s, stops mean {:.2}",
+        seed,
+        stats.spawned,
+        stats.delay.count,
+        stats.delay.mean / seconds,
+        stats.delay.p95 / seconds,
+        stats.delay.max / seconds,
+        stats.stops.mean
+    );
+    println!(
+        "pedestrians: {} seen, wait mean {:.1}s p95 {:.1}s max {:.1}s",
+        stats.pedestrian_wait.count,
+        stats.pedestrian_wait.mean / seconds,
+        stats.pedestrian_wait.p95 / seconds,
+        stats.pedestrian_wait.max / seconds
+    );
+}
+
+/*
+Runs every scenario with every seed, writing the outputs of each run into <output>/<scenario>/seed-<seed> and one summary line per run into <output>/summary.csv. Without scenarios the defaults are run, and without seeds every run gets its scenario's seed or a random one.
+*/
+fn batch(scenarios: &[PathBuf], seeds: &[u64], options: &Options) -> Result<(), String> {
+    if options.record.is_some() {
+        return Err(
+            "--record: a batch cannot be recorded, record its runs one at a time".to_string(),
+        );
+    }
+    if options.frames.is_some() || options.gif.is_some() {
+        return Err(
+            "--frames/--gif: a batch cannot be rendered, render its runs one at a time".to_string(),
+        );
+    }
+    let scenarios: Vec<Option<&Path>> = if scenarios.is_empty() {
+        vec![None]
+    } else {
+        scenarios.iter().map(|path| Some(path.as_path())).collect()
+    };
+    let seeds: Vec<Option<u64>> = if seeds.is_empty() {
+        vec![None]
+    } else {
+        seeds.iter().copied().map(Some).collect()
+    };
+    let mut summary = vec![
+        "scenario,seed,spawned,through,delay_mean_s,delay_p95_s,stops_mean,pedestrian_wait_mean_s"
+            .to_string(),
+    ];
+    let seconds = defs::TICKS_PER_SECOND as f64;
+    for scenario in &scenarios {
+        let name = scenario
+            .and_then(Path::file_stem)
+            .map_or("default".to_string(), |stem| {
+                stem.to_string_lossy().to_string()
+            });
+        for seed in &seeds {
+            let mut session = Session::new(
+                *scenario,
+                *seed,
+                &Options {
+                    output: None,
+                    ..options.clone()
+                },
+            )?;
+            if let Some(output) = &options.output {
+                let dir = output
+                    .join(&name)
+                    .join(format!("seed-{}", session.road.seed));
+                session.exporter = Some(
+                    defs::Exporter::create(&dir, options.format)
+                        .map_err(|error| format!("{}: {error}", dir.display()))?,
+                );
+                session.output = Some(dir);
+            }
+            session.verbose = false;
+            session.run_headless(options.speed)?;
+            let stats = session.finish()?;
+            let line = format!(
+                "{},{},{},{},{:.2},{:.2},{:.3},{:.2}",
+                name,
+                session.road.seed,
+                stats.spawned,
+                stats.delay.count,
+                stats.delay.mean / seconds,
+                stats.delay.p95 / seconds,
+                stats.stops.mean,
+                stats.pedestrian_wait.mean / seconds
+            );
+            println!("{line}");
+            summary.push(line);
+        }
+    }
+    if let Some(output) = &options.output {
+        std::fs::create_dir_all(output).map_err(|error| error.to_string())?;
+        let path = output.join("summary.csv");
+        std::fs::write(&path, summary.join("\n") + "\n")
+            .map_err(|error| format!("{}: {error}", path.display()))?;
+    }
+    Ok(())
+}
+
+/*
+Handles the keys that control the pace of the simulation, in a run as in a replay, and returns whether the key was one of them:
+
+Space pauses and resumes the simulation.
+S simulates a single tick while paused, to follow the controller's decisions one tick at a time.
++ and - double and halve the time scale of the clock, within MIN_TIME_SCALE and MAX_TIME_SCALE.
+*/
+#[cfg(feature = "render")]
+fn pace_key(
+    session: &mut Session,
+    keycode: Keycode,
+    paused: &mut bool,
+    clock: &mut defs::Clock,
+) -> Result<bool, String> {
+    match keycode {
+        Keycode::Space => *paused = !*paused,
+        Keycode::S if *paused && !session.is_over() => session.step()?,
+        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => {
+            clock.set_time_scale(clock.time_scale * 2.0)
+        }
+        Keycode::Minus | Keycode::KpMinus => clock.set_time_scale(clock.time_scale / 2.0),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/*
+Handles a key pressed while a recording is played back in the window, where keys no longer spawn anything:
+
+Left and Right seek ten seconds back and forward, Home goes back to the start, and 0 to 9 seek to that tenth of the recording.
+*/
+#[cfg(feature = "render")]
+fn replay_key(session: &mut Session, keycode: Keycode) -> Result<(), String> {
+    let jump = 10 * defs::TICKS_PER_SECOND as u64;
+    let end = session.replay.as_ref().map_or(0, defs::Recording::end);
+    let digit = keycode.into_i32() - Keycode::Num0.into_i32();
+    match keycode {
+        Keycode::Left => session.seek(session.road.tick.saturating_sub(jump))?,
+        Keycode::Right => session.seek(session.road.tick + jump)?,
+        Keycode::Home => session.seek(0)?,
+        _ if (0..10).contains(&digit) => session.seek(end * digit as u64 / 10)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/*
+Returns the text of the indicator in the top right corner: whether the simulation runs, is paused or has reached its end, whether it is a replay, and the time scale.
+*/
+#[cfg(feature = "render")]
+fn pace_indicator(session: &Session, paused: bool, time_scale: f64) -> String {
+    let state = if session.is_over() {
+        "END"
+    } else if paused {
+        "PAUSED"
+    } else {
+        "RUNNING"
+    };
+    let mode = if session.replay.is_some() {
+        "REPLAY "
+    } else {
+        ""
+    };
+    format!("{mode}{state} {time_scale}X")
+}
 
 /*
 Initializes the SDL library for graphics and event handling.
 Creates a window with a title and sets up a canvas for drawing.
-Creates a Road object, which represents the road intersection, and initializes it with default values.
-Enters the main simulation loop, which runs indefinitely until the user closes the window or presses the Escape key.
+Enters the main simulation loop, which runs until the user closes the window or presses the Escape key, or until the session's duration has been simulated. Rendering and simulation are decoupled: a Clock at the given time scale turns the real time each frame took into a number of fixed simulation steps, so the simulated time does not depend on the frame rate.
 
 Inside the loop, the program:
 Handles events such as key presses and window closures.
-Adds new cars to the road intersection based on user input (e.g., pressing the Left, Up, Right, or Down arrow keys).
-Clears the canvas and redraws all cars, roads, and traffic lights.
-Updates the simulation state by calling the simulation_loop method on the Road object.
-Presents the updated canvas and waits for the next frame.
+Pauses, single-steps and changes the time scale with the keys of pace_key, and seeks with those of replay_key during a replay.
+Adds new cars to the road intersection based on user input (e.g., pressing the Left, Up, Right, or Down arrow keys, or E for an emergency vehicle), and P adds a pedestrian at a random crosswalk.
+Clears the canvas and redraws all cars, roads, crosswalks, pedestrians and traffic lights.
+Updates the simulation state by stepping the session as many times as the clock says, unless it is paused, and draws the HUD of hud_lines, which H shows and hides, and the pace indicator.
+Presents the updated canvas and waits for the rest of the frame.
 */
-fn main() {
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
+#[cfg(feature = "render")]
+fn run_window(session: &mut Session, speed: f64) -> Result<(), String> {
+    let sdl_context = sdl2::init()?;
+    let video_subsystem = sdl_context.video()?;
 
     let window = video_subsystem
-        .window("Road Intersection Simulation", 800, 800)
+        .window(
+            "Road Intersection Simulation",
+            session.road.geometry.width as u32,
+            session.road.geometry.height as u32,
+        )
         .position_centered()
         .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas().build().unwrap();
-    let mut event_pump = sdl_context.event_pump().unwrap();
+        .map_err(|error| error.to_string())?;
 
-    let mut all = defs::Road::new();
+    let mut canvas = window
+        .into_canvas()
+        .build()
+        .map_err(|error| error.to_string())?;
+    let mut event_pump = sdl_context.event_pump()?;
+    let mut clock = defs::Clock::new(speed);
+    let mut paused = false;
+    let mut hud = true;
+    let mut last_frame = Instant::now();
 
     'running: loop {
+        if session.is_over() && session.replay.is_none() {
+            break 'running;
+        }
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
@@ -46,247 +632,183 @@ fn main() {
                     ..
                 } => break 'running,
 
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if pace_key(session, keycode, &mut paused, &mut clock)? => {}
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::H),
+                    ..
+                } => hud = !hud,
+
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if session.replay.is_some() => replay_key(session, keycode)?,
+
                 Event::KeyDown {
                     keycode: Some(Keycode::Left),
                     ..
                 } => {
-                    all.cars_before_stop_east
-                        .push(defs::Car::new(defs::Side::FromEast));
+                    session.road.record_key("left");
+                    let car = session.road.generate_car(defs::Side::FromEast);
+                    session.road.spawn(car);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Up),
                     ..
                 } => {
-                    all.cars_before_stop_south
-                        .push(defs::Car::new(defs::Side::FromSouth));
+                    session.road.record_key("up");
+                    let car = session.road.generate_car(defs::Side::FromSouth);
+                    session.road.spawn(car);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Right),
                     ..
                 } => {
-                    all.cars_before_stop_west
-                        .push(defs::Car::new(defs::Side::FromWest));
+                    session.road.record_key("right");
+                    let car = session.road.generate_car(defs::Side::FromWest);
+                    session.road.spawn(car);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::Down),
                     ..
                 } => {
-                    all.cars_before_stop_north
-                        .push(defs::Car::new(defs::Side::FromNorth));
+                    session.road.record_key("down");
+                    let car = session.road.generate_car(defs::Side::FromNorth);
+                    session.road.spawn(car);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::R),
                     ..
                 } => {
-                    let rnd = defs::Car::random_car();
-                    match rnd.side {
-                        defs::Side::FromEast => all.cars_before_stop_east.push(rnd),
-                        defs::Side::FromNorth => all.cars_before_stop_north.push(rnd),
-                        defs::Side::FromSouth => all.cars_before_stop_south.push(rnd),
-                        defs::Side::FromWest => all.cars_before_stop_west.push(rnd),
-                    }
+                    session.road.record_key("r");
+                    let side = defs::Side::random(&mut session.road.rng);
+                    let car = session.road.generate_car(side);
+                    session.road.spawn(car);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    ..
+                } => {
+                    session.road.record_key("e");
+                    let side = defs::Side::random(&mut session.road.rng);
+                    let car = defs::Car::emergency(side, &mut session.road.rng);
+                    session.road.spawn(car);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } if session.road.manager.is_none() => {
+                    session.road.record_key("p");
+                    let leg = defs::Side::random(&mut session.road.rng);
+                    let forward = session.road.rng.random();
+                    session.road.spawn_pedestrian(defs::Pedestrian::new(
+                        leg,
+                        forward,
+                        session.road.tick,
+                    ));
                 }
                 _ => {}
             }
         }
 
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
-        canvas.clear();
-
-        for x in &all.cars_after_stop_east {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-        for x in &all.cars_after_stop_west {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-        for x in &all.cars_after_stop_north {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-        for x in &all.cars_after_stop_south {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-        for x in &all.cars_in_intersection {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-        for x in &all.cars_before_stop_east {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-        for x in &all.cars_before_stop_west {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-        for x in &all.cars_before_stop_north {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-        for x in &all.cars_before_stop_south {
-            canvas.set_draw_color(x.color);
-            canvas
-                .fill_rect(Rect::new(
-                    x.x as i32,
-                    x.y as i32,
-                    defs::CAR_WIDTH as u32,
-                    defs::CAR_HEIGHT as u32,
-                ))
-                .unwrap();
-        }
-
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
-        canvas
-            .draw_rect(Rect::new(
-                380,
-                0,
-                defs::CAR_WIDTH as u32,
-                defs::OUTPUT_HEIGHT as u32,
-            ))
-            .unwrap();
-        canvas
-            .draw_rect(Rect::new(
-                400,
-                0,
-                defs::CAR_WIDTH as u32,
-                defs::OUTPUT_HEIGHT as u32,
-            ))
-            .unwrap();
-        canvas
-            .draw_rect(Rect::new(
-                0,
-                380,
-                defs::OUTPUT_WIDTH as u32,
-                defs::CAR_HEIGHT as u32,
-            ))
-            .unwrap();
-        canvas
-            .draw_rect(Rect::new(
-                0,
-                400,
-                defs::OUTPUT_WIDTH as u32,
-                defs::CAR_HEIGHT as u32,
-            ))
-            .unwrap();
-
-        if all.north_lights.color == defs::Light::Green {
-            canvas.set_draw_color(Color::RGB(0, 255, 0));
-        } else {
-            canvas.set_draw_color(Color::RGB(255, 0, 0));
-        }
-        canvas
-            .draw_rect(Rect::new(
-                defs::OUTPUT_WIDTH / 2 - 2 * defs::CAR_WIDTH,
-                defs::OUTPUT_HEIGHT / 2 - 2 * defs::CAR_HEIGHT,
-                defs::CAR_WIDTH as u32,
-                defs::CAR_HEIGHT as u32,
-            ))
-            .unwrap();
-
-        if all.east_lights.color == defs::Light::Green {
-            canvas.set_draw_color(Color::RGB(0, 255, 0));
+        let elapsed = last_frame.elapsed();
+        last_frame = Instant::now();
+        if paused {
+            clock.reset();
         } else {
-            canvas.set_draw_color(Color::RGB(255, 0, 0));
-        }
-        canvas
-            .draw_rect(Rect::new(
-                defs::OUTPUT_WIDTH / 2 - 2 * defs::CAR_WIDTH,
-                defs::OUTPUT_HEIGHT / 2 + defs::CAR_HEIGHT,
-                defs::CAR_WIDTH as u32,
-                defs::CAR_HEIGHT as u32,
-            ))
-            .unwrap();
-
-        if all.south_lights.color == defs::Light::Green {
-            canvas.set_draw_color(Color::RGB(0, 255, 0));
-        } else {
-            canvas.set_draw_color(Color::RGB(255, 0, 0));
-        }
-        canvas
-            .draw_rect(Rect::new(
-                defs::OUTPUT_WIDTH / 2 + defs::CAR_WIDTH,
-                defs::OUTPUT_HEIGHT / 2 + defs::CAR_HEIGHT,
-                defs::CAR_WIDTH as u32,
-                defs::CAR_HEIGHT as u32,
-            ))
-            .unwrap();
-
-        if all.west_lights.color == defs::Light::Green {
-            canvas.set_draw_color(Color::RGB(0, 255, 0));
-        } else {
-            canvas.set_draw_color(Color::RGB(255, 0, 0));
+            for _ in 0..clock.advance(elapsed.as_secs_f64()) {
+                if session.is_over() {
+                    break;
+                }
+                session.step()?;
+            }
         }
-        canvas
-            .draw_rect(Rect::new(
-                defs::OUTPUT_WIDTH / 2 + defs::CAR_WIDTH,
-                defs::OUTPUT_HEIGHT / 2 - 2 * defs::CAR_HEIGHT,
-                defs::CAR_WIDTH as u32,
-                defs::CAR_HEIGHT as u32,
-            ))
-            .unwrap();
 
-        all.simulation_loop();
+        defs::draw_road(&mut canvas, &session.road);
+        if hud {
+            defs::draw_hud(&mut canvas, &session.road);
+        }
+        defs::draw_indicator(
+            &mut canvas,
+            session.road.geometry.width,
+            &pace_indicator(session, paused, clock.time_scale),
+        );
 
         canvas.present();
 
-        std::thread::sleep(Duration::from_millis(1000 / 60));
+        if let Some(rest) = Duration::from_millis(1000 / 60).checked_sub(last_frame.elapsed()) {
+            std::thread::sleep(rest);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "render"))]
+fn run_window(_session: &mut Session, _speed: f64) -> Result<(), String> {
+    Err("this binary was built without the render feature, use headless or rebuild with --features render".to_string())
+}
+
+/*
+Parses the command line and runs the subcommand. The seed is printed at start, and the statistics of the run from Road::stats at the end.
+All randomness comes from the Road's generator, seeded with --seed or by the scenario, so the same seed with the same key presses replays the same run.
+*/
+fn main() {
+    let cli = Cli::parse();
+    let command = match cli.command {
+        Some(command) => command,
+        None => Cli::parse_from(["road_intersection_raid", "run"])
+            .command
+            .expect("run is a subcommand"),
+    };
+    let result = match command {
+        Command::Run { source, options } => {
+            Session::new(source.scenario.as_deref(), source.seed, &options).and_then(
+                |mut session| {
+                    println!("seed {}", session.road.seed);
+                    run_window(&mut session, options.speed.unwrap_or(1.0))?;
+                    let stats = session.finish()?;
+                    print_stats(session.road.seed, &stats);
+                    Ok(())
+                },
+            )
+        }
+        Command::Headless { source, options } => {
+            Session::new(source.scenario.as_deref(), source.seed, &options).and_then(
+                |mut session| {
+                    println!("seed {}", session.road.seed);
+                    session.run_headless(options.speed)?;
+                    let stats = session.finish()?;
+                    print_stats(session.road.seed, &stats);
+                    Ok(())
+                },
+            )
+        }
+        Command::Batch {
+            scenarios,
+            seeds,
+            options,
+        } => batch(&scenarios, &seeds, &options),
+        Command::Replay {
+            recording,
+            start,
+            options,
+        } => Session::replay(&recording, start, &options).and_then(|mut session| {
+            println!("seed {}", session.road.seed);
+            if cfg!(feature = "render") {
+                run_window(&mut session, options.speed.unwrap_or(1.0))?;
+            } else {
+                session.run_headless(options.speed)?;
+            }
+            let stats = session.finish()?;
+            print_stats(session.road.seed, &stats);
+            Ok(())
+        }),
+    };
+    if let Err(error) = result {
+        eprintln!("error: {error}");
+        std::process::exit(1);
     }
 }
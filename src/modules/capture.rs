@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::constants::*;
+use super::draw::*;
+use super::road::*;
+use super::se_base::*;
+
+/*
+A Frame is an offscreen software surface: width by height pixels in memory, three bytes (red, green, blue) per pixel row by row from the top left. It needs no display, no SDL and no video driver, which is what offscreen rendering in CI runs relies on.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new(width: i32, height: i32) -> Frame {
+        Frame {
+            width,
+            height,
+            pixels: vec![0; (width.max(0) * height.max(0) * 3) as usize],
+        }
+    }
+
+    /*
+    Draws the road with its HUD into a new frame the size of the canvas of its geometry, the size of the window.
+    */
+    pub fn of(road: &Road) -> Frame {
+        let mut frame = Frame::new(road.geometry.width, road.geometry.height);
+        draw_road(&mut frame, road);
+        draw_hud(&mut frame, road);
+        frame
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Rgb> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        let at = ((y * self.width + x) * 3) as usize;
+        Some(Rgb::new(
+            self.pixels[at],
+            self.pixels[at + 1],
+            self.pixels[at + 2],
+        ))
+    }
+
+    pub fn save_png(&self, path: &Path) -> Result<(), String> {
+        let error = |err: &dyn std::fmt::Display| format!("{}: {}", path.display(), err);
+        let file = File::create(path).map_err(|err| error(&err))?;
+        let mut encoder =
+            png::Encoder::new(BufWriter::new(file), self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(|err| error(&err))?;
+        writer
+            .write_image_data(&self.pixels)
+            .map_err(|err| error(&err))
+    }
+
+    /*
+    Turns the frame into a GIF frame. The drawing uses a handful of colors, so they are given an exact palette; a frame with more than 256 colors is quantized instead.
+    */
+    pub fn to_gif(&self) -> gif::Frame<'static> {
+        let mut palette: HashMap<[u8; 3], u8> = HashMap::new();
+        let mut indices = Vec::with_capacity(self.pixels.len() / 3);
+        for pixel in self.pixels.chunks_exact(3) {
+            let color = [pixel[0], pixel[1], pixel[2]];
+            let index = match palette.get(&color) {
+                Some(index) => *index,
+                None if palette.len() == 256 => {
+                    return gif::Frame::from_rgb_speed(
+                        self.width as u16,
+                        self.height as u16,
+                        &self.pixels,
+                        10,
+                    );
+                }
+                None => {
+                    let index = palette.len() as u8;
+                    palette.insert(color, index);
+                    index
+                }
+            };
+            indices.push(index);
+        }
+        let mut colors = vec![0; palette.len() * 3];
+        for (color, index) in palette {
+            colors[index as usize * 3..index as usize * 3 + 3].copy_from_slice(&color);
+        }
+        gif::Frame::from_palette_pixels(
+            self.width as u16,
+            self.height as u16,
+            indices,
+            colors,
+            None,
+        )
+    }
+}
+
+impl Surface for Frame {
+    fn clear(&mut self, color: Rgb) {
+        for pixel in self.pixels.chunks_exact_mut(3) {
+            pixel.copy_from_slice(&[color.r, color.g, color.b]);
+        }
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Rgb) {
+        let left = x.max(0);
+        let right = (x + width).min(self.width);
+        for row in y.max(0)..(y + height).min(self.height) {
+            for column in left..right {
+                let at = ((row * self.width + column) * 3) as usize;
+                self.pixels[at..at + 3].copy_from_slice(&[color.r, color.g, color.b]);
+            }
+        }
+    }
+}
+
+/*
+The Capture struct renders a run offscreen while it is simulated, one frame every `every` ticks:
+
+frames: the directory every frame is written into as a PNG named after its tick, frame-00001234.png
+gif: the animated GIF the frames are encoded into as the run goes, each shown for the simulated time it covers
+
+Either can be left out, and the GIF is as large as the canvas it is created for. A GIF frame lasts every ticks rounded to hundredths of a second, and at least two hundredths, the shortest delay viewers respect, so frames taken more often than 50 times a simulated second play back slower than real time.
+*/
+pub struct Capture {
+    pub every: u64,
+    pub frames: Option<PathBuf>,
+    pub gif: Option<gif::Encoder<BufWriter<File>>>,
+    pub captured: u64,
+}
+
+impl Capture {
+    pub fn new(
+        every: u64,
+        (width, height): (i32, i32),
+        frames: Option<&Path>,
+        gif: Option<&Path>,
+    ) -> Result<Capture, String> {
+        if every == 0 {
+            return Err("a frame must be taken at least every tick".to_string());
+        }
+        if let Some(dir) = frames {
+            std::fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+        }
+        let gif = gif
+            .map(|path| {
+                let error = |err: &dyn std::fmt::Display| format!("{}: {}", path.display(), err);
+                let file = File::create(path).map_err(|err| error(&err))?;
+                let mut encoder =
+                    gif::Encoder::new(BufWriter::new(file), width as u16, height as u16, &[])
+                        .map_err(|err| error(&err))?;
+                encoder
+                    .set_repeat(gif::Repeat::Infinite)
+                    .map_err(|err| error(&err))?;
+                Ok::<_, String>(encoder)
+            })
+            .transpose()?;
+        Ok(Capture {
+            every,
+            frames: frames.map(Path::to_path_buf),
+            gif,
+            captured: 0,
+        })
+    }
+
+    /*
+    Renders the road when its tick is a multiple of every, and writes the frame out.
+    */
+    pub fn capture(&mut self, road: &Road) -> Result<(), String> {
+        if !road.tick.is_multiple_of(self.every) {
+            return Ok(());
+        }
+        let frame = Frame::of(road);
+        if let Some(dir) = &self.frames {
+            frame.save_png(&dir.join(format!("frame-{:08}.png", road.tick)))?;
+        }
+        if let Some(encoder) = self.gif.as_mut() {
+            let mut gif_frame = frame.to_gif();
+            let delay = (self.every as f64 * SIMULATION_STEP * 100.0).round() as u16;
+            gif_frame.delay = delay.max(2);
+            encoder
+                .write_frame(&gif_frame)
+                .map_err(|err| err.to_string())?;
+        }
+        self.captured += 1;
+        Ok(())
+    }
+
+    /*
+    Ends the GIF with its trailer and flushes it to disk.
+    */
+    pub fn finish(&mut self) -> Result<(), String> {
+        if let Some(encoder) = self.gif.take() {
+            let mut out = encoder.into_inner().map_err(|err| err.to_string())?;
+            out.flush().map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
@@ -1,233 +1,365 @@
 extern crate rand;
 
+use super::conflict::*;
 use super::constants::*;
+use super::geometry::*;
+use super::idm::*;
 use super::se_base::*;
+use super::speeds::*;
+use super::trajectory::*;
+use super::vehicle::*;
 
 use rand::Rng;
-use sdl2::pixels::Color;
 
 /*
 The Car struct represents a car object with the following fields:
 
-x and y: the car's position coordinates
+id: a number identifying the car, assigned by Road::spawn
+x and y: the car's position coordinates, the top left corner of the lane-sized square holding the front of the vehicle, in pixels with sub-pixel precision
 color: the car's color
 direction: the car's direction (Left, Right, or Straight)
 side: the side of the road the car is coming from (FromEast, FromNorth, etc.)
-velocity: the car's speed
+class: the vehicle class, which sets the length and width of the vehicle (its body extends backwards from the front square) and its performance
+speed: the car's current speed in pixels per simulated second, which the car-following model changes smoothly
+heading: the angle the body of the car points at, in radians on the canvas, 0 towards the right and PI / 2 downwards
+trajectory: the path of the car's front bumper through its movement
+travelled: how far along its trajectory the front bumper is, in pixels
+idm: the car-following parameters of the car
+waiting: whether the car has been held at the stop line, which decides how it reacts to an amber light
+aside: how far the car has pulled over to the curb, sideways from its lane, to let an emergency vehicle by, in pixels
+
+The car moves by advancing travelled; x, y and heading follow from its place on the trajectory and are only rounded to whole pixels when the car is drawn.
 */
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Car {
-    pub x: i32,
-    pub y: i32,
-    pub color: Color,
+    pub id: u64,
+    pub x: f64,
+    pub y: f64,
+    pub color: Rgb,
     pub direction: Direction,
     pub side: Side,
-    pub velocity: i32,
+    pub class: VehicleClass,
+    pub length: i32,
+    pub width: i32,
+    pub speed: f64,
+    pub heading: f64,
+    pub trajectory: Trajectory,
+    pub travelled: f64,
+    pub idm: Idm,
+    pub waiting: bool,
+    pub aside: f64,
 }
 
 impl Car {
     /*
-    Constructor function that creates a new instance of the Car struct. It takes a side parameter of type Side (an enum representing the direction a car is coming from) and returns a Car object with randomly generated attributes.
+    Constructor function that creates a new instance of the Car struct. It takes a side parameter of type Side (an enum representing the direction a car is coming from) and returns a Car object with attributes drawn from the given random number generator, usually the Road's.
+
+    Its vehicle class is drawn from the default TrafficMix, and the rest is done by Car::with_class.
+    */
+    pub fn new<R: Rng + ?Sized>(side: Side, rng: &mut R) -> Car {
+        let class = TrafficMix::default().sample(rng);
+        Car::with_class(side, class, rng)
+    }
+
+    /*
+    Creates a car of the given vehicle class coming from the given side, turning Left, Straight or Right with equal chances, and the rest is done by Car::with_route.
+    */
+    pub fn with_class<R: Rng + ?Sized>(side: Side, class: VehicleClass, rng: &mut R) -> Car {
+        let direction = Direction::ALL[rng.random_range(0..3)];
+        Car::with_route(side, direction, class, rng)
+    }
+
+    /*
+    Creates a car of the given vehicle class coming from the given side and going in the given direction, with a speed drawn from the default SpeedDistribution. The rest is done by Car::with_speed.
+    */
+    pub fn with_route<R: Rng + ?Sized>(
+        side: Side,
+        direction: Direction,
+        class: VehicleClass,
+        rng: &mut R,
+    ) -> Car {
+        let speed = SpeedDistribution::default().sample(rng);
+        Car::with_speed(side, direction, class, speed)
+    }
+
+    /*
+    Creates a car of the given vehicle class coming from the given side and going in the given direction, with its own length, width and car-following parameters.
 
-    It generates a random number between 0 and 2, which determines the car's direction (left, straight, or right) and color.
-    It generates a random velocity between MIN_VELOCITY and MAX_VELOCITY.
-    It sets the car's initial position (x and y coordinates) and returns a new Car object with the generated attributes.
+    Its color is the color of its direction, or CAR_COLOR_EMERGENCY for an emergency vehicle.
+    The speed, in pixels per second, becomes the desired speed of its car-following model, capped at the class's maximum speed. The car arrives driving at that speed.
+    It sets the car's trajectory through the default Geometry, puts the car at its start and returns a new Car object with these attributes. Road::spawn routes it through the road's own geometry.
+    */
+    pub fn with_speed(side: Side, direction: Direction, class: VehicleClass, speed: f64) -> Car {
+        let color = match direction {
+            _ if class == VehicleClass::Emergency => CAR_COLOR_EMERGENCY,
+            Direction::Left => CAR_COLOR_LEFT,
+            Direction::Straight => CAR_COLOR_STRAIGHT,
+            Direction::Right => CAR_COLOR_RIGHT,
+        };
+        let spec = class.spec();
+        let idm = class.idm(speed);
+        let mut car = Car {
+            id: 0,
+            x: 0.0,
+            y: 0.0,
+            color,
+            direction,
+            side,
+            class,
+            length: spec.length,
+            width: spec.width,
+            speed: idm.desired_speed,
+            heading: side_heading(side),
+            trajectory: Trajectory::new(&Geometry::default(), Movement::new(side, direction)),
+            travelled: 0.0,
+            idm,
+            waiting: false,
+            aside: 0.0,
+        };
+        car.place();
+        car
+    }
+
+    /*
+    Lays the car's trajectory out through the given intersection and puts the car back at its start.
+    */
+    pub fn route(&mut self, geometry: &Geometry) {
+        self.trajectory = Trajectory::new(geometry, self.movement());
+        self.travelled = 0.0;
+        self.place();
+    }
+
+    /*
+    Creates an emergency vehicle coming from the given side. It is drawn in CAR_COLOR_EMERGENCY instead of the color of its route.
+    */
+    pub fn emergency<R: Rng + ?Sized>(side: Side, rng: &mut R) -> Car {
+        Car::with_class(side, VehicleClass::Emergency, rng)
+    }
+
+    pub fn is_emergency(&self) -> bool {
+        self.class == VehicleClass::Emergency
+    }
+
+    /*
+    Generates a random Car object coming from one of four directions (East, North, South, or West) with randomly assigned attributes (direction, color, speed, etc.) using the Car::new constructor.
     */
-    pub fn new(side: Side) -> Car {
-        let mut rng = rand::rng();
-        let random_number = rng.random_range(0..3);
-        let velocity = rng.random_range(MIN_VELOCITY..MAX_VELOCITY);
-        let direction: Direction;
-        let color: Color;
+    pub fn random_car<R: Rng + ?Sized>(rng: &mut R) -> Car {
+        let random_number = rng.random_range(0..4);
         match random_number {
-            0 => {
-                direction = Direction::Left;
-                color = CAR_COLOR_LEFT;
-            }
-            1 => {
-                direction = Direction::Straight;
-                color = CAR_COLOR_STRAIGHT;
-            }
-            _ => {
-                direction = Direction::Right;
-                color = CAR_COLOR_RIGHT;
-            }
+            0 => Car::new(Side::FromEast, rng),
+            1 => Car::new(Side::FromNorth, rng),
+            2 => Car::new(Side::FromSouth, rng),
+            _ => Car::new(Side::FromWest, rng),
         }
-        match side {
-            Side::FromEast => {
-                let x = 0;
-                let y = OUTPUT_HEIGHT / 2;
-                return Car {
-                    x,
-                    y,
-                    color,
-                    direction,
-                    side,
-                    velocity,
-                };
-            }
-            Side::FromNorth => {
-                let x = OUTPUT_WIDTH / 2 - CAR_WIDTH;
-                let y = 0;
-                return Car {
-                    x,
-                    y,
-                    color,
-                    direction,
-                    side,
-                    velocity,
-                };
-            }
-            Side::FromSouth => {
-                let x = OUTPUT_WIDTH / 2;
-                let y = OUTPUT_HEIGHT - CAR_HEIGHT;
-                return Car {
-                    x,
-                    y,
-                    color,
-                    direction,
-                    side,
-                    velocity,
-                };
-            }
-            Side::FromWest => {
-                let x = OUTPUT_WIDTH - CAR_WIDTH;
-                let y = OUTPUT_HEIGHT / 2 - CAR_HEIGHT;
-                return Car {
-                    x,
-                    y,
-                    color,
-                    direction,
-                    side,
-                    velocity,
-                };
+    }
+
+    /*
+    Returns the distance in pixels between the car and its stop line, measured along its approach. It is zero or negative once the car has reached the line.
+    The stop line is set back from the intersection box by the crosswalk of the car's leg, and the car stops the hold of its trajectory short of it, where long vehicles turning from other sides do not sweep over it.
+    */
+    pub fn distance_to_stop_line(&self) -> f64 {
+        self.stop_point() - self.travelled
+    }
+
+    /*
+    Returns the distance along its trajectory at which the car stops for its stop line, the hold short of it.
+    */
+    pub fn stop_point(&self) -> f64 {
+        self.trajectory.stop_line - self.trajectory.hold
+    }
+
+    /*
+    Returns whether the car has reached its stop line, within STOP_LINE_TOLERANCE. A car braking for the line comes ever closer to it without quite getting there, so the last fraction of a pixel counts as being at the line.
+    */
+    pub fn at_stop_line(&self) -> bool {
+        self.distance_to_stop_line() <= STOP_LINE_TOLERANCE
+    }
+
+    /*
+    Returns how far the car has travelled since crossing its stop line, following its path around a turn. It is zero while the car is still on its approach.
+    */
+    pub fn distance_past_stop_line(&self) -> f64 {
+        (-self.distance_to_stop_line()).max(0.0)
+    }
+
+    pub fn movement(&self) -> Movement {
+        Movement::new(self.side, self.direction)
+    }
+
+    /*
+    Returns whether the tail of the car has crossed the far edge of the intersection box on the leg its movement exits by, which is how a car in the intersection is known to have left it.
+    */
+    pub fn has_left_intersection(&self) -> bool {
+        self.travelled > self.trajectory.exit(self.length as f64)
+    }
+
+    /*
+    Returns whether the tail of the car has passed the end of the road it exits by, after which it is gone from the canvas.
+    */
+    pub fn has_left_road(&self) -> bool {
+        self.travelled - self.length as f64 > self.trajectory.end
+    }
+
+    /*
+    Returns the four corners of the vehicle's body on the canvas, rotated to its heading. The front bumper sits on the trajectory and the body, the vehicle's length long and width wide, extends backwards from it, moved towards the curb by aside while the car is pulled over.
+    */
+    pub fn body(&self) -> [Point; 4] {
+        let body = self
+            .trajectory
+            .body(self.travelled, self.length as f64, self.width as f64);
+        if self.aside == 0.0 {
+            return body;
+        }
+        let (dx, dy) = self.trajectory.curb();
+        body.map(|(x, y)| (x + dx * self.aside, y + dy * self.aside))
+    }
+
+    /*
+    Returns whether the car is in its lane, where the cars behind it have to follow it. A car that has pulled over all the way to the curb is out of their way.
+    */
+    pub fn in_lane(&self) -> bool {
+        self.aside < self.trajectory.lane_width
+    }
+
+    /*
+    Returns the speed in pixels per second the car may drive at on a free road where it is: its desired speed, lowered to the turn speed of its trajectory on the turn and to the speed it can comfortably brake down to the turn speed from before it.
+    */
+    pub fn speed_limit(&self) -> f64 {
+        let desired = self.idm.desired_speed;
+        match self.trajectory.turn_speed() {
+            Some(turn) if self.travelled <= self.trajectory.turn_end() => {
+                let ahead = (self.trajectory.stop_line - self.travelled).max(0.0);
+                desired.min((turn * turn + 2.0 * self.idm.comfortable_deceleration * ahead).sqrt())
             }
+            _ => desired,
         }
     }
 
     /*
-    Generates a random Car object coming from one of four directions (East, North, South, or West) with randomly assigned attributes (direction, color, velocity, etc.) using the Car::new constructor.
+    The stop/go decision a car makes when its light turns amber.
+
+    A car that is already waiting at the line stays put. A moving car keeps going only if it is too close to stop at its comfortable deceleration and it can still reach the line before the amber runs out. Every other car stops.
     */
-    pub fn random_car() -> Car {
-        let mut rng = rand::rng();
-        let random_number = rng.random_range(0..4);
-        match random_number {
-            0 => Car::new(Side::FromEast),
-            1 => Car::new(Side::FromNorth),
-            2 => Car::new(Side::FromSouth),
-            _ => Car::new(Side::FromWest),
+    pub fn go_on_amber(&self, feu: &TrafficLight) -> bool {
+        if self.waiting {
+            return false;
         }
+        let distance = self.distance_to_stop_line();
+        let step = self.speed * SIMULATION_STEP;
+        distance < self.idm.stopping_distance(self.speed) + step
+            && distance <= step * feu.remaining as f64
     }
 
     /*
-    It updates the position of a car based on its current side, direction, velocity, and the state of a traffic light (feu).
-
-    The function uses a match statement to determine the car's side and then applies different movement rules based on that side and the car's direction. The movement rules take into account the car's velocity, the traffic light's color, and the car's position relative to the center of the screen.
-
-    In general, the function allows the car to move forward if the traffic light is green, and stops or redirects the car if the light is red, depending on the car's direction and position.
-    */
-    pub fn moove(&mut self, feu: TrafficLight) {
-        match self.side {
-            Side::FromEast => {
-                if self.x + self.velocity < OUTPUT_WIDTH / 2 - 2 * CAR_WIDTH {
-                    self.x += self.velocity;
-                } else if self.x + self.velocity < OUTPUT_WIDTH / 2 - CAR_WIDTH {
-                    if feu.color == Light::Green {
-                        self.x += self.velocity;
-                    } else {
-                        self.x = OUTPUT_WIDTH / 2 - 2 * CAR_WIDTH;
-                    }
-                } else {
-                    match self.direction {
-                        Direction::Left => {
-                            self.x = OUTPUT_WIDTH / 2;
-                            self.y -= self.velocity;
-                        }
-                        Direction::Right => {
-                            self.x = OUTPUT_WIDTH / 2 - CAR_WIDTH;
-                            self.y += self.velocity;
-                        }
-                        Direction::Straight => {
-                            self.x += self.velocity;
-                        }
-                    }
-                }
-            }
-            Side::FromNorth => {
-                if self.y + self.velocity < OUTPUT_HEIGHT / 2 - 2 * CAR_HEIGHT {
-                    self.y += self.velocity;
-                } else if self.y + self.velocity < OUTPUT_HEIGHT / 2 - CAR_HEIGHT {
-                    if feu.color == Light::Green {
-                        self.y += self.velocity;
-                    } else {
-                        self.y = OUTPUT_HEIGHT / 2 - 2 * CAR_HEIGHT;
-                    }
-                } else {
-                    match self.direction {
-                        Direction::Left => {
-                            self.y = OUTPUT_HEIGHT / 2;
-                            self.x += self.velocity;
-                        }
-                        Direction::Right => {
-                            self.y = OUTPUT_HEIGHT / 2 - CAR_HEIGHT;
-                            self.x -= self.velocity;
-                        }
-                        Direction::Straight => {
-                            self.y += self.velocity;
-                        }
-                    }
-                }
-            }
-            Side::FromWest => {
-                if self.x - self.velocity > OUTPUT_WIDTH / 2 + 2 * CAR_WIDTH {
-                    self.x -= self.velocity;
-                } else if self.x - self.velocity > OUTPUT_WIDTH / 2 {
-                    if feu.color == Light::Green {
-                        self.x -= self.velocity;
-                    } else {
-                        self.x = OUTPUT_WIDTH / 2 + CAR_WIDTH;
-                    }
-                } else {
-                    match self.direction {
-                        Direction::Left => {
-                            self.x = OUTPUT_WIDTH / 2 - CAR_WIDTH;
-                            self.y += self.velocity;
-                        }
-                        Direction::Right => {
-                            self.x = OUTPUT_WIDTH / 2;
-                            self.y -= self.velocity;
-                        }
-                        Direction::Straight => {
-                            self.x -= self.velocity;
-                        }
-                    }
-                }
-            }
-            Side::FromSouth => {
-                if self.y - self.velocity > OUTPUT_HEIGHT / 2 + 2 * CAR_HEIGHT {
-                    self.y -= self.velocity;
-                } else if self.y - self.velocity > OUTPUT_HEIGHT / 2 {
-                    if feu.color == Light::Green {
-                        self.y -= self.velocity;
-                    } else {
-                        self.y = OUTPUT_HEIGHT / 2 + CAR_HEIGHT;
-                    }
-                } else {
-                    match self.direction {
-                        Direction::Left => {
-                            self.y = OUTPUT_HEIGHT / 2 - CAR_HEIGHT;
-                            self.x -= self.velocity;
-                        }
-                        Direction::Right => {
-                            self.y = OUTPUT_HEIGHT / 2;
-                            self.x += self.velocity;
-                        }
-                        Direction::Straight => {
-                            self.y -= self.velocity;
-                        }
-                    }
-                }
+    Returns whether the car may cross its stop line under the given light: always on green, on amber only when go_on_amber says so, never on red.
+    */
+    pub fn may_pass(&self, feu: &TrafficLight) -> bool {
+        match feu.color {
+            Light::Green => true,
+            Light::Amber => self.go_on_amber(feu),
+            Light::Red => false,
+        }
+    }
+
+    /*
+    Returns the bumper to bumper gap between the car and a leader driving ahead of it in the same lane, taking the leader's length into account.
+    */
+    pub fn gap_to(&self, leader: &Car) -> f64 {
+        (self.x - leader.x).abs() + (self.y - leader.y).abs() - leader.length as f64
+    }
+
+    /*
+    Drives the car for one tick, SIMULATION_STEP seconds of simulated time.
+
+    The car-following model gives the acceleration from the current speed and what is ahead: leader is the gap to the obstacle ahead and its speed, or None on a free road. The car aims for its speed_limit rather than its desired speed, so it slows down for a turn. The speed changes by that acceleration over the step and moove carries the car along its trajectory by the distance covered, never bringing it closer than the minimum gap to its leader.
+    */
+    pub fn follow(&mut self, leader: Option<(f64, f64)>, feu: TrafficLight) {
+        let idm = Idm {
+            desired_speed: self.speed_limit(),
+            ..self.idm.clone()
+        };
+        let acceleration = idm.acceleration(self.speed, leader);
+        let speed = (self.speed + acceleration * SIMULATION_STEP).max(0.0);
+        let mut travel = (self.speed + speed) / 2.0 * SIMULATION_STEP;
+        self.speed = speed;
+        if let Some((gap, _)) = leader {
+            let room = (gap - self.idm.min_gap).max(0.0);
+            if travel > room {
+                travel = room;
+                self.speed = self.speed.min(room / SIMULATION_STEP);
             }
         }
+        self.moove(travel, feu);
+    }
+
+    /*
+    It moves the car by travel pixels along its trajectory, given the state of a traffic light (feu).
+
+    A car that may not pass the light is held at its stop line: the function allows the car to cross it only if the traffic light is green, or amber when the car decides to go. Past the line the car follows the straight path or the arc of its turn into its exit lane.
+    */
+    pub fn moove(&mut self, mut travel: f64, feu: TrafficLight) {
+        let to_line = self.distance_to_stop_line();
+        if to_line >= 0.0 && travel > to_line && !self.may_pass(&feu) {
+            travel = to_line;
+        }
+        self.travelled += travel;
+        self.place();
+    }
+
+    /*
+    Sets x, y and heading from the car's place on its trajectory. The heading is the direction of the body and the lane-sized square holding the front of the vehicle is centered behind the front bumper.
+    */
+    fn place(&mut self) {
+        let [a, b, c, d] = self.body();
+        let front = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        let back = ((c.0 + d.0) / 2.0, (c.1 + d.1) / 2.0);
+        self.heading = (front.1 - back.1).atan2(front.0 - back.0);
+        let half = self.trajectory.lane_width / 2.0;
+        self.x = front.0 - half * self.heading.cos() - half;
+        self.y = front.1 - half * self.heading.sin() - half;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /*
+    Returns a passenger car driving at the given speed, the given distance short of its stop line.
+    */
+    fn approaching(distance: f64, speed: f64) -> Car {
+        let mut car = Car::with_speed(
+            Side::FromNorth,
+            Direction::Straight,
+            VehicleClass::PassengerCar,
+            speed,
+        );
+        car.route(&Geometry::default());
+        car.travelled = car.trajectory.stop_line - distance;
+        car
+    }
+
+    #[test]
+    fn only_a_car_too_close_to_stop_goes_on_amber() {
+        let amber = TrafficLight {
+            color: Light::Amber,
+            remaining: AMBER_TICKS,
+        };
+        let speed = 150.0;
+        let stopping = approaching(0.0, speed).idm.stopping_distance(speed);
+        assert!(approaching(stopping / 2.0, speed).may_pass(&amber));
+        assert!(!approaching(stopping * 2.0, speed).may_pass(&amber));
+
+        let ending = TrafficLight {
+            remaining: 1,
+            ..amber
+        };
+        assert!(!approaching(stopping / 2.0, speed).may_pass(&ending));
+
+        let mut waiting = approaching(0.0, 0.0);
+        waiting.waiting = true;
+        assert!(!waiting.may_pass(&amber));
+        assert!(waiting.may_pass(&TrafficLight::new(Light::Green)));
+        assert!(!waiting.may_pass(&TrafficLight::new(Light::Red)));
     }
 }
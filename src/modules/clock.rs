@@ -0,0 +1,56 @@
+use super::constants::*;
+
+/*
+The Clock paces the simulation against real time. Road::simulation_loop always advances the simulation by SIMULATION_STEP seconds, so the Clock only decides how many steps to run for the real time that went by:
+
+time_scale: the simulated seconds per real second, between MIN_TIME_SCALE and MAX_TIME_SCALE
+accumulator: the simulated time owed to the simulation but not yet run, always less than one step after advance
+
+A frame that took longer than MAX_FRAME_SECONDS, because the window was dragged or the simulation cannot keep up, only counts for MAX_FRAME_SECONDS, so the simulation slows down instead of running ever more steps to catch up.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Clock {
+    pub time_scale: f64,
+    pub accumulator: f64,
+}
+
+impl Clock {
+    pub fn new(time_scale: f64) -> Clock {
+        Clock {
+            time_scale: Clock::clamp(time_scale),
+            accumulator: 0.0,
+        }
+    }
+
+    /*
+    Brings a time scale within MIN_TIME_SCALE and MAX_TIME_SCALE.
+    */
+    pub fn clamp(time_scale: f64) -> f64 {
+        if time_scale.is_nan() {
+            return 1.0;
+        }
+        time_scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE)
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f64) {
+        self.time_scale = Clock::clamp(time_scale);
+    }
+
+    /*
+    Adds the given real seconds, scaled by the time scale, to the accumulator and returns the number of whole steps to simulate for them. What is left of a step is kept for the next call.
+    */
+    pub fn advance(&mut self, real_seconds: f64) -> u32 {
+        let real_seconds = real_seconds.clamp(0.0, MAX_FRAME_SECONDS);
+        self.accumulator += real_seconds * self.time_scale;
+        let steps = (self.accumulator / SIMULATION_STEP).floor();
+        self.accumulator -= steps * SIMULATION_STEP;
+        steps as u32
+    }
+
+    /*
+    Forgets the time owed to the simulation, for when it has been paused.
+    */
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
@@ -0,0 +1,237 @@
+use super::geometry::*;
+use super::se_base::*;
+use super::trajectory::*;
+use super::vehicle::*;
+
+/*
+The intersection box is made of cells, one per lane crossing, a lane width square each. A cell is named by its column, counted from the left edge of the box, and its row, counted from the top edge, so a box of single-lane roads has the four cells (0, 0) to (1, 1).
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cell {
+    pub column: i32,
+    pub row: i32,
+}
+
+impl Cell {
+    /*
+    Returns every cell of the intersection box of the given geometry, row by row from the top left.
+    */
+    pub fn all(geometry: &Geometry) -> Vec<Cell> {
+        let count = 2 * geometry.lanes;
+        (0..count)
+            .flat_map(|row| (0..count).map(move |column| Cell { column, row }))
+            .collect()
+    }
+
+    /*
+    Returns the cells a car reserves in reservation mode: the cells of the intersection box and around it, as many rows and columns on every side as it takes to cover the crosswalks in front of the stop lines.
+    */
+    pub fn reserved(geometry: &Geometry) -> Vec<Cell> {
+        let count = 2 * geometry.lanes;
+        let ring = (geometry.setback + geometry.lane_width - 1) / geometry.lane_width;
+        (-ring..count + ring)
+            .flat_map(|row| (-ring..count + ring).map(move |column| Cell { column, row }))
+            .collect()
+    }
+
+    /*
+    Returns the top left corner of the cell on the canvas.
+    */
+    pub fn origin(&self, geometry: &Geometry) -> (i32, i32) {
+        let (left, top, _, _) = geometry.intersection_box();
+        (
+            left + self.column * geometry.lane_width,
+            top + self.row * geometry.lane_width,
+        )
+    }
+
+    /*
+    Returns whether a convex polygon, such as the body of a vehicle, covers part of the cell.
+    */
+    pub fn overlaps(&self, geometry: &Geometry, polygon: &[Point]) -> bool {
+        let (x, y) = self.origin(geometry);
+        let size = geometry.lane_width as f64;
+        let cell = rectangle(x as f64, y as f64, size, size);
+        polygons_overlap(&cell, polygon)
+    }
+}
+
+/*
+A Movement is the combination of the side a car comes from and the direction it takes through the intersection.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Movement {
+    pub side: Side,
+    pub direction: Direction,
+}
+
+impl Movement {
+    pub const ALL: [Movement; 12] = {
+        let mut all = [Movement::new(Side::FromNorth, Direction::Left); 12];
+        let mut i = 0;
+        while i < 12 {
+            all[i] = Movement::new(Side::ALL[i / 3], Direction::ALL[i % 3]);
+            i += 1;
+        }
+        all
+    };
+
+    pub const fn new(side: Side, direction: Direction) -> Movement {
+        Movement { side, direction }
+    }
+
+    /*
+    Returns the position of the movement in Movement::ALL, three per side in the order of Side::index and Direction::index.
+    */
+    pub fn index(&self) -> usize {
+        self.side.index() * 3 + self.direction.index()
+    }
+
+    /*
+    Returns the leg the movement leaves the intersection by, named after the Side whose cars enter on that leg. It decides which cars_after_stop_* queue the car joins.
+    */
+    pub fn exit_leg(&self) -> Side {
+        match (self.side, self.direction) {
+            (Side::FromEast, Direction::Left)
+            | (Side::FromSouth, Direction::Straight)
+            | (Side::FromWest, Direction::Right) => Side::FromNorth,
+            (Side::FromSouth, Direction::Left)
+            | (Side::FromWest, Direction::Straight)
+            | (Side::FromNorth, Direction::Right) => Side::FromEast,
+            (Side::FromWest, Direction::Left)
+            | (Side::FromNorth, Direction::Straight)
+            | (Side::FromEast, Direction::Right) => Side::FromSouth,
+            (Side::FromNorth, Direction::Left)
+            | (Side::FromEast, Direction::Straight)
+            | (Side::FromSouth, Direction::Right) => Side::FromWest,
+        }
+    }
+}
+
+/*
+The conflict matrix of an intersection. Two movements from different sides conflict when the areas the longest vehicle class sweeps along their trajectories overlap, as worked out by swept_conflicts from the design_sweeps of the intersection's geometry.
+Movements from the same side never conflict: they either use the same lane and are kept apart by the following distance, or keep to lanes of their own all the way through.
+holds is, for every vehicle class indexed by VehicleClass::index, how far short of its stop line a vehicle of each movement waits while a vehicle of the class makes a movement from another side, from swept_holds, so that it does not stand in the way where a long vehicle cuts or swings over its approach when turning. They are 0 for the classes short enough to keep clear of the stop lines, every class but trucks and articulated buses in the default geometry.
+asides is how far short of its stop line a vehicle of each side may pull over to the curb for an emergency vehicle, from swept_asides, so that it does not stand in the way of a long vehicle turning there.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConflictMatrix {
+    pub conflicts: [[bool; 12]; 12],
+    pub holds: [[[f64; 12]; 12]; 6],
+    pub asides: [f64; 4],
+}
+
+impl Default for ConflictMatrix {
+    fn default() -> ConflictMatrix {
+        ConflictMatrix::new(&Geometry::default())
+    }
+}
+
+impl ConflictMatrix {
+    pub fn new(geometry: &Geometry) -> ConflictMatrix {
+        let sweeps = design_sweeps(geometry);
+        let mut holds = [[[0.0; 12]; 12]; 6];
+        for class in VehicleClass::REGULAR
+            .into_iter()
+            .chain([VehicleClass::Emergency])
+        {
+            holds[class.index()] = swept_holds(geometry, &class_sweeps(geometry, class));
+        }
+        ConflictMatrix {
+            conflicts: swept_conflicts(&sweeps),
+            holds,
+            asides: swept_asides(geometry, &sweeps),
+        }
+    }
+
+    pub fn between(&self, movement: &Movement, other: &Movement) -> bool {
+        self.conflicts[movement.index()][other.index()]
+    }
+
+    /*
+    Returns how far short of its stop line a vehicle making the waiting movement stands while a vehicle of the given class makes the driving movement.
+    */
+    pub fn hold(&self, class: VehicleClass, driving: &Movement, waiting: &Movement) -> f64 {
+        self.holds[class.index()][driving.index()][waiting.index()]
+    }
+
+    pub fn aside(&self, side: Side) -> f64 {
+        self.asides[side.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movement(side: Side, direction: Direction) -> Movement {
+        Movement::new(side, direction)
+    }
+
+    #[test]
+    fn the_matrix_is_symmetric_and_spares_each_side() {
+        let matrix = ConflictMatrix::default();
+        for a in Movement::ALL {
+            for b in Movement::ALL {
+                assert_eq!(matrix.between(&a, &b), matrix.between(&b, &a));
+                if a.side == b.side {
+                    assert!(!matrix.between(&a, &b), "{:?} {:?}", a, b);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compatible_movements_share_the_intersection() {
+        let matrix = ConflictMatrix::default();
+        let north = |direction| movement(Side::FromNorth, direction);
+        let south = |direction| movement(Side::FromSouth, direction);
+        let east = |direction| movement(Side::FromEast, direction);
+        assert!(!matrix.between(&north(Direction::Straight), &south(Direction::Straight)));
+        assert!(!matrix.between(&north(Direction::Right), &south(Direction::Right)));
+        assert!(matrix.between(&north(Direction::Left), &south(Direction::Straight)));
+        assert!(matrix.between(&north(Direction::Straight), &east(Direction::Straight)));
+        assert!(matrix.between(&north(Direction::Straight), &east(Direction::Left)));
+    }
+
+    #[test]
+    fn only_long_vehicles_turning_left_hold_other_sides_back() {
+        let matrix = ConflictMatrix::default();
+        for class in VehicleClass::REGULAR
+            .into_iter()
+            .chain([VehicleClass::Emergency])
+        {
+            let long = matches!(class, VehicleClass::Truck | VehicleClass::ArticulatedBus);
+            for driving in Movement::ALL {
+                let holds: Vec<f64> = Movement::ALL
+                    .iter()
+                    .map(|waiting| matrix.hold(class, &driving, waiting))
+                    .collect();
+                if long && driving.direction == Direction::Left {
+                    assert!(
+                        holds.iter().any(|hold| *hold > 0.0),
+                        "{:?} {:?}",
+                        class,
+                        driving
+                    );
+                } else {
+                    assert!(
+                        holds.iter().all(|hold| *hold == 0.0),
+                        "{:?} {:?}",
+                        class,
+                        driving
+                    );
+                }
+                for waiting in Movement::ALL.iter().filter(|m| m.side == driving.side) {
+                    assert_eq!(matrix.hold(class, &driving, waiting), 0.0);
+                }
+            }
+        }
+        let bus = Movement::new(Side::FromEast, Direction::Left);
+        let waiting = Movement::new(Side::FromNorth, Direction::Straight);
+        assert!(
+            matrix.hold(VehicleClass::ArticulatedBus, &bus, &waiting)
+                > matrix.hold(VehicleClass::Truck, &bus, &waiting)
+        );
+    }
+}
@@ -1,12 +1,46 @@
-use sdl2::pixels::Color;
+use super::se_base::Rgb;
 
 pub const OUTPUT_WIDTH: i32 = 800;
 pub const OUTPUT_HEIGHT: i32 = 800;
-pub const MIN_VELOCITY: i32 = 2;
-pub const MAX_VELOCITY: i32 = 3;
+pub const MIN_SPEED: f64 = 120.0;
+pub const MAX_SPEED: f64 = 180.0;
 pub const CAR_WIDTH: i32 = 20;
 pub const CAR_HEIGHT: i32 = 20;
 pub const SECURITY_DISTANCE: i32 = 30;
-pub const CAR_COLOR_LEFT: Color = Color::RGB(255, 0, 0);
-pub const CAR_COLOR_RIGHT: Color = Color::RGB(0, 255, 0);
-pub const CAR_COLOR_STRAIGHT: Color = Color::RGB(0, 0, 255);
+pub const CROSSWALK_WIDTH: i32 = 20;
+pub const CROSSWALK_CURB: i32 = 10;
+pub const PEDESTRIAN_SIZE: i32 = 6;
+pub const PEDESTRIAN_SPEED: f64 = 9.0;
+pub const TICKS_PER_SECOND: u32 = 60;
+pub const SIMULATION_STEP: f64 = 1.0 / TICKS_PER_SECOND as f64;
+pub const MIN_TIME_SCALE: f64 = 0.25;
+pub const MAX_TIME_SCALE: f64 = 100.0;
+pub const MAX_FRAME_SECONDS: f64 = 0.25;
+pub const MIN_GREEN_TICKS: u32 = 3 * TICKS_PER_SECOND;
+pub const GREEN_TICKS: u32 = 10 * TICKS_PER_SECOND;
+pub const AMBER_TICKS: u32 = 3 * TICKS_PER_SECOND;
+pub const ALL_RED_TICKS: u32 = TICKS_PER_SECOND;
+pub const PREEMPTION_DETECTION_DISTANCE: i32 = 300;
+pub const PULL_ASIDE_SPEED: f64 = 20.0;
+pub const RESERVATION_REQUEST_DISTANCE: i32 = 100;
+pub const RESERVATION_MARGIN_TICKS: u64 = 5;
+pub const STATS_SAMPLE_TICKS: u64 = TICKS_PER_SECOND as u64;
+pub const STOPPED_SPEED: f64 = 6.0;
+pub const STOP_LINE_TOLERANCE: f64 = 0.5;
+pub const MAX_LATERAL_ACCELERATION: f64 = 120.0;
+pub const SWEEP_STEP: f64 = 1.0;
+pub const OVERLAP_TOLERANCE: f64 = 1.0;
+pub const CAR_COLOR_LEFT: Rgb = Rgb::new(255, 0, 0);
+pub const CAR_COLOR_RIGHT: Rgb = Rgb::new(0, 255, 0);
+pub const CAR_COLOR_STRAIGHT: Rgb = Rgb::new(0, 0, 255);
+pub const CAR_COLOR_EMERGENCY: Rgb = Rgb::new(255, 255, 255);
+pub const PEDESTRIAN_COLOR: Rgb = Rgb::new(255, 255, 0);
+pub const TEXT_COLOR: Rgb = Rgb::new(255, 255, 255);
+pub const GLYPH_WIDTH: i32 = 5;
+pub const GLYPH_HEIGHT: i32 = 7;
+pub const TEXT_SCALE: i32 = 2;
+pub const IDM_MAX_ACCELERATION: f64 = 180.0;
+pub const IDM_COMFORTABLE_DECELERATION: f64 = 288.0;
+pub const IDM_TIME_HEADWAY: f64 = 0.25;
+pub const IDM_MIN_GAP: f64 = (SECURITY_DISTANCE - CAR_WIDTH) as f64;
+pub const IDM_DELTA: i32 = 4;
@@ -0,0 +1,251 @@
+use super::se_base::*;
+use super::timing::*;
+
+/*
+The Observation struct is what a SignalController sees of the intersection on each tick:
+
+queues: the number of cars waiting in each cars_before_stop_* queue, indexed by Side::index
+occupancy: the number of cars currently inside the intersection
+elapsed: the number of simulation ticks since the Road was created
+lights: the light state that was applied on the previous tick
+pedestrians: the number of pedestrians waiting at or walking over each crosswalk, indexed by Side::index of its leg
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observation {
+    pub queues: [usize; 4],
+    pub occupancy: usize,
+    pub elapsed: u64,
+    pub lights: SignalState,
+    pub pedestrians: [usize; 4],
+}
+
+impl Observation {
+    pub fn queue(&self, side: Side) -> usize {
+        self.queues[side.index()]
+    }
+
+    /*
+    Returns the number of pedestrians on the crosswalks that walk alongside the given approach, the crosswalks of the two legs on either side of it.
+    */
+    pub fn pedestrians_alongside(&self, side: Side) -> usize {
+        Side::ALL
+            .iter()
+            .filter(|leg| **leg != side && **leg != side.opposite())
+            .map(|leg| self.pedestrians[leg.index()])
+            .sum()
+    }
+}
+
+/*
+The SignalState struct holds one TrafficLight per approach. It is returned by a SignalController and copied onto the Road lights.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalState {
+    pub north: TrafficLight,
+    pub east: TrafficLight,
+    pub south: TrafficLight,
+    pub west: TrafficLight,
+}
+
+impl SignalState {
+    pub fn all_red() -> SignalState {
+        SignalState {
+            north: TrafficLight::new(Light::Red),
+            east: TrafficLight::new(Light::Red),
+            south: TrafficLight::new(Light::Red),
+            west: TrafficLight::new(Light::Red),
+        }
+    }
+
+    /*
+    Returns a state where only the given approach is green and every other approach is red.
+    */
+    pub fn green_for(side: Side) -> SignalState {
+        let mut state = SignalState::all_red();
+        state.light_mut(side).color = Light::Green;
+        state
+    }
+
+    pub fn light(&self, side: Side) -> &TrafficLight {
+        match side {
+            Side::FromNorth => &self.north,
+            Side::FromEast => &self.east,
+            Side::FromSouth => &self.south,
+            Side::FromWest => &self.west,
+        }
+    }
+
+    pub fn light_mut(&mut self, side: Side) -> &mut TrafficLight {
+        match side {
+            Side::FromNorth => &mut self.north,
+            Side::FromEast => &mut self.east,
+            Side::FromSouth => &mut self.south,
+            Side::FromWest => &mut self.west,
+        }
+    }
+
+    pub fn any_green(&self) -> bool {
+        Side::ALL
+            .iter()
+            .any(|side| self.light(*side).color == Light::Green)
+    }
+}
+
+/*
+The SignalController trait is the extension point for traffic control strategies.
+Road::simulation_loop builds an Observation on every tick, passes it to the controller and applies the returned SignalState to its four traffic lights.
+A controller must also be Clone, which gives it clone_box through CloneController, so that a Road holding it as a trait object can be cloned.
+*/
+pub trait SignalController: std::fmt::Debug + CloneController {
+    fn update(&mut self, observation: &Observation) -> SignalState;
+}
+
+/*
+Clones a controller behind a Box<dyn SignalController>. It is implemented for every SignalController that is Clone.
+*/
+pub trait CloneController {
+    fn clone_box(&self) -> Box<dyn SignalController>;
+}
+
+impl<T: SignalController + Clone + 'static> CloneController for T {
+    fn clone_box(&self) -> Box<dyn SignalController> {
+        Box::new(self.clone())
+    }
+}
+
+impl Clone for Box<dyn SignalController> {
+    fn clone(&self) -> Box<dyn SignalController> {
+        self.clone_box()
+    }
+}
+
+/*
+The default controller. It runs a PhaseTimer and, whenever the previous phase has cleared, gives green to the approach with the longest queue.
+Ties are broken in the order east, west, north, south. While no car is waiting, pedestrians call the first approach, in the same order, that walks alongside them, and every light stays red when nobody is waiting at all.
+A green lasts at least the minimum green and at most the phase's green duration; in between it is cut short as soon as its queue is empty, no pedestrian is crossing alongside it and another approach has cars waiting.
+*/
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LongestQueue {
+    pub timer: PhaseTimer,
+}
+
+impl LongestQueue {
+    pub fn new(timing: SignalTiming) -> LongestQueue {
+        LongestQueue {
+            timer: PhaseTimer::new(timing),
+        }
+    }
+}
+
+impl SignalController for LongestQueue {
+    fn update(&mut self, observation: &Observation) -> SignalState {
+        self.timer.advance();
+        if self.timer.is_idle() {
+            let mut best = Side::FromEast;
+            for side in [Side::FromWest, Side::FromNorth, Side::FromSouth] {
+                if observation.queue(side) > observation.queue(best) {
+                    best = side;
+                }
+            }
+            if observation.queue(best) > 0 {
+                self.timer.start(best);
+            } else if let Some(side) = [
+                Side::FromEast,
+                Side::FromWest,
+                Side::FromNorth,
+                Side::FromSouth,
+            ]
+            .into_iter()
+            .find(|side| observation.pedestrians_alongside(*side) > 0)
+            {
+                self.timer.start(side);
+            }
+        } else if let Some(side) = self.timer.phase {
+            let others_waiting = Side::ALL
+                .iter()
+                .any(|other| *other != side && observation.queue(*other) > 0);
+            if observation.queue(side) == 0
+                && observation.pedestrians_alongside(side) == 0
+                && others_waiting
+            {
+                self.timer.end_green();
+            }
+        }
+        self.timer.state()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(queues: [usize; 4], pedestrians: [usize; 4]) -> Observation {
+        Observation {
+            queues,
+            occupancy: 0,
+            elapsed: 0,
+            lights: SignalState::all_red(),
+            pedestrians,
+        }
+    }
+
+    fn green(state: &SignalState) -> Vec<Side> {
+        Side::ALL
+            .into_iter()
+            .filter(|side| state.light(*side).color == Light::Green)
+            .collect()
+    }
+
+    #[test]
+    fn the_longest_queue_gets_the_green() {
+        let mut controller = LongestQueue::default();
+        let mut queues = [0; 4];
+        queues[Side::FromSouth.index()] = 3;
+        queues[Side::FromWest.index()] = 2;
+        let state = controller.update(&observation(queues, [0; 4]));
+        assert_eq!(green(&state), vec![Side::FromSouth]);
+
+        let mut controller = LongestQueue::default();
+        let state = controller.update(&observation([1; 4], [0; 4]));
+        assert_eq!(green(&state), vec![Side::FromEast]);
+    }
+
+    #[test]
+    fn nobody_waiting_keeps_every_light_red() {
+        let mut controller = LongestQueue::default();
+        for _ in 0..10 {
+            let state = controller.update(&observation([0; 4], [0; 4]));
+            assert_eq!(state, SignalState::all_red());
+        }
+        let mut pedestrians = [0; 4];
+        pedestrians[Side::FromNorth.index()] = 1;
+        let state = controller.update(&observation([0; 4], pedestrians));
+        assert_eq!(green(&state), vec![Side::FromEast]);
+    }
+
+    #[test]
+    fn an_empty_green_ends_after_the_minimum_when_others_wait() {
+        let timing = SignalTiming::default();
+        let mut controller = LongestQueue::new(timing.clone());
+        let mut queues = [0; 4];
+        queues[Side::FromNorth.index()] = 1;
+        controller.update(&observation(queues, [0; 4]));
+        let mut queues = [0; 4];
+        queues[Side::FromEast.index()] = 1;
+        let mut greens = 1;
+        while controller.update(&observation(queues, [0; 4])).north.color == Light::Green {
+            greens += 1;
+        }
+        assert_eq!(greens, timing.min_green);
+        assert_eq!(controller.timer.interval, Interval::Amber);
+
+        let mut controller = LongestQueue::new(timing.clone());
+        let mut queues = [0; 4];
+        queues[Side::FromNorth.index()] = 1;
+        let mut greens = 0;
+        while controller.update(&observation(queues, [0; 4])).north.color == Light::Green {
+            greens += 1;
+        }
+        assert_eq!(greens, timing.green[Side::FromNorth.index()]);
+    }
+}
@@ -0,0 +1,268 @@
+use rand::Rng;
+
+use super::constants::*;
+use super::se_base::*;
+
+/*
+The distribution of the time between two arrivals on an approach, for a mean headway h given by the flow rate:
+
+Poisson: exponential headways with mean h, which makes the arrivals a Poisson process
+ShiftedExponential: headways of at least min_headway ticks, plus an exponential part that keeps the mean at h; the minimum keeps arriving vehicles apart
+Uniform: headways drawn uniformly between h / 2 and 3h / 2
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Headway {
+    Poisson,
+    ShiftedExponential { min_headway: f64 },
+    Uniform,
+}
+
+impl Headway {
+    /*
+    Draws one headway in ticks for the given mean headway. When the mean is below the minimum of a shifted exponential, the minimum is used.
+    */
+    pub fn sample<R: Rng + ?Sized>(&self, mean: f64, rng: &mut R) -> f64 {
+        let exponential = |mean: f64, rng: &mut R| -mean * (1.0 - rng.random::<f64>()).ln();
+        match *self {
+            Headway::Poisson => exponential(mean, rng),
+            Headway::ShiftedExponential { min_headway } => {
+                min_headway + exponential((mean - min_headway).max(0.0), rng)
+            }
+            Headway::Uniform => rng.random_range(mean / 2.0..=mean * 3.0 / 2.0),
+        }
+    }
+}
+
+/*
+One corner of a DemandProfile: from the given tick on, the flow rate in vehicles per hour.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FlowPoint {
+    pub tick: u64,
+    pub flow: f64,
+}
+
+/*
+A piecewise linear flow rate over time. Between two points the flow goes linearly from one to the other, before the first point it is the flow of the first point and after the last point the flow of the last point.
+An AM peak, for example, ramps up from 300 to 900 vehicles per hour over ten minutes, stays there for half an hour and ramps back down.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct DemandProfile {
+    pub points: Vec<FlowPoint>,
+}
+
+impl DemandProfile {
+    pub fn new(points: Vec<FlowPoint>) -> DemandProfile {
+        DemandProfile { points }
+    }
+
+    pub fn constant(flow: f64) -> DemandProfile {
+        DemandProfile::new(vec![FlowPoint { tick: 0, flow }])
+    }
+
+    /*
+    Checks that the profile has at least one point, that its ticks increase and that no flow is negative.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        if self.points.is_empty() {
+            return Err("points: a profile needs at least one point".to_string());
+        }
+        for (i, point) in self.points.iter().enumerate() {
+            if !(point.flow >= 0.0 && point.flow.is_finite()) {
+                return Err(format!(
+                    "points[{}].flow: {} vehicles per hour is not a flow",
+                    i, point.flow
+                ));
+            }
+            if i > 0 && point.tick <= self.points[i - 1].tick {
+                return Err(format!(
+                    "points[{}].tick: {} does not come after the previous point at {}",
+                    i,
+                    point.tick,
+                    self.points[i - 1].tick
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    Returns the flow rate in vehicles per hour at the given tick.
+    */
+    pub fn flow_at(&self, tick: u64) -> f64 {
+        let Some(first) = self.points.first() else {
+            return 0.0;
+        };
+        if tick <= first.tick {
+            return first.flow;
+        }
+        for pair in self.points.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if tick < to.tick {
+                let share = (tick - from.tick) as f64 / (to.tick - from.tick) as f64;
+                return from.flow + (to.flow - from.flow) * share;
+            }
+        }
+        self.points[self.points.len() - 1].flow
+    }
+}
+
+/*
+The generated demand of one approach: vehicles arrive at side following the flow rate of profile, with headways drawn from headway.
+until_next is the number of vehicles the flow still has to bring before the next arrival, once it has been drawn. Counting headways in vehicles rather than ticks lets a headway drawn at a low flow shrink when the flow ramps up.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApproachDemand {
+    pub side: Side,
+    pub profile: DemandProfile,
+    pub headway: Headway,
+    pub until_next: Option<f64>,
+}
+
+impl ApproachDemand {
+    pub fn new(side: Side, profile: DemandProfile, headway: Headway) -> ApproachDemand {
+        ApproachDemand {
+            side,
+            profile,
+            headway,
+            until_next: None,
+        }
+    }
+
+    /*
+    Returns whether a vehicle arrives at the given tick. Each tick brings flow / 3600 / TICKS_PER_SECOND vehicles, and a vehicle arrives once they add up to the drawn headway; the next headway is then drawn from the flow rate at that tick.
+    The first headway is drawn on the first tick with a positive flow, and a zero flow holds the arrivals until the flow picks up again.
+    */
+    pub fn arrives<R: Rng + ?Sized>(&mut self, tick: u64, rng: &mut R) -> bool {
+        let flow = self.profile.flow_at(tick);
+        if flow <= 0.0 {
+            return false;
+        }
+        let mean = 3600.0 * TICKS_PER_SECOND as f64 / flow;
+        let until_next = self
+            .until_next
+            .get_or_insert_with(|| self.headway.sample(mean, rng) / mean);
+        *until_next -= 1.0 / mean;
+        if *until_next > 0.0 {
+            return false;
+        }
+        *until_next += self.headway.sample(mean, rng) / mean;
+        true
+    }
+}
+
+/*
+The generated demand of the whole intersection, one ApproachDemand per approach that has one. Road::simulation_loop spawns the arrivals into the cars_before_stop_* queues on every tick.
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Demand {
+    pub approaches: Vec<ApproachDemand>,
+}
+
+impl Demand {
+    /*
+    Creates a demand with the same constant flow rate and headway distribution on all four approaches.
+    */
+    pub fn uniform(flow: f64, headway: Headway) -> Demand {
+        Demand {
+            approaches: Side::ALL
+                .iter()
+                .map(|side| ApproachDemand::new(*side, DemandProfile::constant(flow), headway))
+                .collect(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (i, approach) in self.approaches.iter().enumerate() {
+            approach
+                .profile
+                .validate()
+                .map_err(|err| format!("approaches[{}].profile.{}", i, err))?;
+            if let Headway::ShiftedExponential { min_headway } = approach.headway
+                && !(min_headway >= 0.0 && min_headway.is_finite())
+            {
+                return Err(format!(
+                    "approaches[{}].headway.min_headway: {} ticks is not a headway",
+                    i, min_headway
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    Returns the sides that get a vehicle at the given tick, in the order of the approaches.
+    */
+    pub fn arrivals<R: Rng + ?Sized>(&mut self, tick: u64, rng: &mut R) -> Vec<Side> {
+        self.approaches
+            .iter_mut()
+            .filter_map(|approach| approach.arrives(tick, rng).then_some(approach.side))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    const HEADWAYS: [Headway; 3] = [
+        Headway::Poisson,
+        Headway::ShiftedExponential { min_headway: 120.0 },
+        Headway::Uniform,
+    ];
+
+    #[test]
+    fn sampled_headways_keep_their_mean() {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let mean = 540.0;
+        let draws = 100_000;
+        for headway in HEADWAYS {
+            let total: f64 = (0..draws).map(|_| headway.sample(mean, &mut rng)).sum();
+            let drawn = total / draws as f64;
+            assert!(
+                (drawn / mean - 1.0).abs() < 0.02,
+                "{:?}: mean headway {:.1} ticks, expected {:.1}",
+                headway,
+                drawn,
+                mean
+            );
+        }
+    }
+
+    #[test]
+    fn arrivals_keep_the_flow_rate() {
+        let flow = 600.0;
+        let hours = 10;
+        let mean = 3600.0 * TICKS_PER_SECOND as f64 / flow;
+        for headway in HEADWAYS {
+            let mut demand = Demand::uniform(flow, headway);
+            let mut rng = ChaCha8Rng::seed_from_u64(7);
+            let mut last = [None; 4];
+            let mut gaps = [vec![], vec![], vec![], vec![]];
+            for tick in 0..hours * 3600 * TICKS_PER_SECOND as u64 {
+                for side in demand.arrivals(tick, &mut rng) {
+                    if let Some(previous) = last[side.index()] {
+                        gaps[side.index()].push((tick - previous) as f64);
+                    }
+                    last[side.index()] = Some(tick);
+                }
+            }
+            for side in Side::ALL {
+                let gaps = &gaps[side.index()];
+                let drawn = gaps.iter().sum::<f64>() / gaps.len() as f64;
+                assert!(
+                    (drawn / mean - 1.0).abs() < 0.05,
+                    "{:?} {:?}: mean headway {:.1} ticks over {} arrivals, expected {:.1}",
+                    headway,
+                    side,
+                    drawn,
+                    gaps.len(),
+                    mean
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use super::car::*;
+use super::constants::*;
+use super::font::*;
+use super::hud::*;
+use super::pedestrian::*;
+use super::road::*;
+use super::se_base::*;
+use super::trajectory::*;
+
+/*
+The Surface trait is what the drawing code needs from whatever it draws on: the SDL window canvas in the binary, or a Frame in memory for offscreen rendering. Coordinates are canvas pixels from the top left corner.
+
+draw_rect draws the one pixel outline of a rectangle, fill_polygon fills a convex polygon such as a vehicle turned to its heading, and text writes a line in the bundled bitmap font; all have default implementations on top of fill_rect.
+*/
+pub trait Surface {
+    fn clear(&mut self, color: Rgb);
+
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Rgb);
+
+    fn draw_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Rgb) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        self.fill_rect(x, y, width, 1, color);
+        self.fill_rect(x, y + height - 1, width, 1, color);
+        self.fill_rect(x, y, 1, height, color);
+        self.fill_rect(x + width - 1, y, 1, height, color);
+    }
+
+    /*
+    Fills a convex polygon one row of pixels at a time: a pixel is filled when its center is inside the polygon, so positions are rounded to whole pixels here.
+    */
+    fn fill_polygon(&mut self, points: &[Point], color: Rgb) {
+        let top = points.iter().map(|p| p.1).fold(f64::INFINITY, f64::min);
+        let bottom = points.iter().map(|p| p.1).fold(f64::NEG_INFINITY, f64::max);
+        for row in (top - 0.5).ceil() as i32..=(bottom - 0.5).floor() as i32 {
+            let y = row as f64 + 0.5;
+            let (mut left, mut right) = (f64::INFINITY, f64::NEG_INFINITY);
+            for (i, &(x1, y1)) in points.iter().enumerate() {
+                let (x2, y2) = points[(i + 1) % points.len()];
+                if (y1 <= y && y < y2) || (y2 <= y && y < y1) {
+                    let x = x1 + (y - y1) / (y2 - y1) * (x2 - x1);
+                    left = left.min(x);
+                    right = right.max(x);
+                }
+            }
+            let (first, last) = ((left - 0.5).ceil() as i32, (right - 0.5).floor() as i32);
+            if first <= last {
+                self.fill_rect(first, row, last - first + 1, 1, color);
+            }
+        }
+    }
+
+    /*
+    Writes a line of text with its top left corner at (x, y), each font pixel drawn as a TEXT_SCALE square.
+    */
+    fn text(&mut self, x: i32, y: i32, text: &str, color: Rgb) {
+        for (px, py) in text_pixels(text) {
+            self.fill_rect(
+                x + px * TEXT_SCALE,
+                y + py * TEXT_SCALE,
+                TEXT_SCALE,
+                TEXT_SCALE,
+                color,
+            );
+        }
+    }
+}
+
+/*
+Returns the color used to draw a traffic light box.
+*/
+pub fn light_color(light: &TrafficLight) -> Rgb {
+    match light.color {
+        Light::Green => Rgb::new(0, 255, 0),
+        Light::Amber => Rgb::new(255, 191, 0),
+        Light::Red => Rgb::new(255, 0, 0),
+    }
+}
+
+/*
+Returns the color used to draw a crosswalk from its pedestrian light. Flashing don't walk blinks twice a second.
+*/
+pub fn pedestrian_light_color(light: PedestrianLight, tick: u64) -> Rgb {
+    let blink = (tick / (TICKS_PER_SECOND as u64 / 4)).is_multiple_of(2);
+    match light {
+        PedestrianLight::Walk => Rgb::new(255, 255, 255),
+        PedestrianLight::FlashingDontWalk if blink => Rgb::new(255, 128, 0),
+        PedestrianLight::FlashingDontWalk => Rgb::new(64, 64, 64),
+        PedestrianLight::DontWalk => Rgb::new(255, 128, 0),
+    }
+}
+
+/*
+Fills the body of a vehicle, turned to its heading, so every vehicle class is drawn at its own length and width.
+*/
+pub fn draw_car<S: Surface + ?Sized>(surface: &mut S, car: &Car) {
+    surface.fill_polygon(&car.body(), car.color);
+}
+
+/*
+Draws the whole intersection on a cleared surface, laid out by the road's geometry: the vehicles, the lane outlines, the crosswalks in the color of their pedestrian lights, the pedestrians and the four traffic light boxes.
+*/
+pub fn draw_road<S: Surface + ?Sized>(surface: &mut S, road: &Road) {
+    surface.clear(Rgb::new(0, 0, 0));
+
+    for leg in [
+        Side::FromEast,
+        Side::FromWest,
+        Side::FromNorth,
+        Side::FromSouth,
+    ] {
+        for car in road.cars_after_stop(leg) {
+            draw_car(surface, car);
+        }
+    }
+    for car in &road.cars_in_intersection {
+        draw_car(surface, car);
+    }
+    for side in [
+        Side::FromEast,
+        Side::FromWest,
+        Side::FromNorth,
+        Side::FromSouth,
+    ] {
+        for car in road.cars_before_stop(side) {
+            draw_car(surface, car);
+        }
+    }
+
+    let geometry = &road.geometry;
+    let (center_x, center_y) = geometry.center();
+    let (lanes, lane_width, reach) = (geometry.lanes, geometry.lane_width, geometry.reach());
+    let white = Rgb::new(255, 255, 255);
+    for lane in -lanes..lanes {
+        surface.draw_rect(
+            center_x + lane * lane_width,
+            center_y - reach,
+            lane_width,
+            2 * reach,
+            white,
+        );
+        surface.draw_rect(
+            center_x - reach,
+            center_y + lane * lane_width,
+            2 * reach,
+            lane_width,
+            white,
+        );
+    }
+
+    for leg in Side::ALL {
+        let (x, y, width, height) = geometry.crosswalk(leg);
+        let color = pedestrian_light_color(road.pedestrian_light(leg), road.tick);
+        surface.draw_rect(x, y, width, height, color);
+    }
+    for pedestrian in &road.pedestrians {
+        let (x, y, width, height) = pedestrian.footprint(geometry);
+        surface.fill_rect(x, y, width, height, PEDESTRIAN_COLOR);
+    }
+
+    let (near, far) = (-geometry.half_box() - lane_width, geometry.half_box());
+    let boxes = [
+        (&road.north_lights, near, near),
+        (&road.east_lights, near, far),
+        (&road.south_lights, far, far),
+        (&road.west_lights, far, near),
+    ];
+    for (light, column, row) in boxes {
+        surface.draw_rect(
+            center_x + column,
+            center_y + row,
+            lane_width,
+            lane_width,
+            light_color(light),
+        );
+    }
+}
+
+/*
+Writes the lines of hud_lines in the top left corner.
+*/
+pub fn draw_hud<S: Surface + ?Sized>(surface: &mut S, road: &Road) {
+    let line_height = (GLYPH_HEIGHT + 3) * TEXT_SCALE;
+    for (index, line) in hud_lines(road).iter().enumerate() {
+        surface.text(10, 10 + index as i32 * line_height, line, TEXT_COLOR);
+    }
+}
+
+/*
+Writes a line of text against the top right corner of a canvas of the given width, where the window shows its pace indicator.
+*/
+pub fn draw_indicator<S: Surface + ?Sized>(surface: &mut S, canvas_width: i32, text: &str) {
+    let width = text_width(text) * TEXT_SCALE;
+    surface.text(canvas_width - width - 10, 10, text, TEXT_COLOR);
+}
+
+/*
+The SDL canvas is a Surface when the render feature is enabled, whether it draws into the window or into an SDL software surface, which also works under SDL's dummy video driver. A failed SDL draw call only spoils the frame it belongs to, so its error is dropped.
+*/
+#[cfg(feature = "render")]
+impl<T: sdl2::render::RenderTarget> Surface for sdl2::render::Canvas<T> {
+    fn clear(&mut self, color: Rgb) {
+        self.set_draw_color(color);
+        sdl2::render::Canvas::clear(self);
+    }
+
+    fn fill_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Rgb) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        self.set_draw_color(color);
+        let rect = sdl2::rect::Rect::new(x, y, width as u32, height as u32);
+        let _ = sdl2::render::Canvas::fill_rect(self, rect);
+    }
+
+    fn draw_rect(&mut self, x: i32, y: i32, width: i32, height: i32, color: Rgb) {
+        if width <= 0 || height <= 0 {
+            return;
+        }
+        self.set_draw_color(color);
+        let rect = sdl2::rect::Rect::new(x, y, width as u32, height as u32);
+        let _ = sdl2::render::Canvas::draw_rect(self, rect);
+    }
+}
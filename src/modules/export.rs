@@ -0,0 +1,407 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::road::*;
+use super::se_base::*;
+use super::stats::*;
+use super::vehicle::*;
+
+/*
+The file format written by an Exporter: comma separated values with a header line, or one JSON object per line.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    JsonLines,
+}
+
+impl ExportFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+        }
+    }
+}
+
+impl std::str::FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<ExportFormat, String> {
+        match name {
+            "csv" => Ok(ExportFormat::Csv),
+            "jsonl" => Ok(ExportFormat::JsonLines),
+            _ => Err(format!(
+                "unknown export format {name}, expected csv or jsonl"
+            )),
+        }
+    }
+}
+
+pub const TRIP_COLUMNS: [&str; 11] = [
+    "id",
+    "side",
+    "direction",
+    "class",
+    "spawned_tick",
+    "arrived_tick",
+    "entered_tick",
+    "exited_tick",
+    "stops",
+    "delay_ticks",
+    "seed",
+];
+pub const LIGHT_COLUMNS: [&str; 10] = [
+    "tick",
+    "north",
+    "north_remaining_ticks",
+    "east",
+    "east_remaining_ticks",
+    "south",
+    "south_remaining_ticks",
+    "west",
+    "west_remaining_ticks",
+    "seed",
+];
+pub const QUEUE_COLUMNS: [&str; 6] = ["tick", "side", "direction", "queue", "throughput", "seed"];
+
+/*
+The Exporter writes the results of a run into three files of a directory, trips, lights and queues, with the extension of its format. Their columns are TRIP_COLUMNS, LIGHT_COLUMNS and QUEUE_COLUMNS, described in the README; times are in ticks and every row ends with the seed of the run.
+
+write_new appends whatever the StatsCollector recorded since the last call: the trips of vehicles that left the intersection, the light state of every tick and the queue samples. Calling it after every tick streams the results while the run goes on; each call only looks at what is new, through the counts of finished trips, light samples and queue samples written so far.
+finish writes what is left, including the trips of vehicles still on their way, and flushes the files. Calling only finish writes everything at exit.
+*/
+pub struct Exporter {
+    pub format: ExportFormat,
+    trips: BufWriter<File>,
+    lights: BufWriter<File>,
+    queues: BufWriter<File>,
+    trips_written: usize,
+    lights_written: usize,
+    samples_written: usize,
+}
+
+impl Exporter {
+    /*
+    Creates the directory if needed and the three files in it, and writes the CSV header lines.
+    */
+    pub fn create(dir: &Path, format: ExportFormat) -> std::io::Result<Exporter> {
+        std::fs::create_dir_all(dir)?;
+        let open = |name: &str| -> std::io::Result<BufWriter<File>> {
+            let path = dir.join(format!("{name}.{}", format.extension()));
+            Ok(BufWriter::new(File::create(path)?))
+        };
+        let mut exporter = Exporter {
+            format,
+            trips: open("trips")?,
+            lights: open("lights")?,
+            queues: open("queues")?,
+            trips_written: 0,
+            lights_written: 0,
+            samples_written: 0,
+        };
+        if format == ExportFormat::Csv {
+            writeln!(exporter.trips, "{}", TRIP_COLUMNS.join(","))?;
+            writeln!(exporter.lights, "{}", LIGHT_COLUMNS.join(","))?;
+            writeln!(exporter.queues, "{}", QUEUE_COLUMNS.join(","))?;
+        }
+        Ok(exporter)
+    }
+
+    pub fn write_new(&mut self, road: &Road) -> std::io::Result<()> {
+        self.write(road, false)
+    }
+
+    pub fn finish(&mut self, road: &Road) -> std::io::Result<()> {
+        self.write(road, true)?;
+        self.trips.flush()?;
+        self.lights.flush()?;
+        self.queues.flush()
+    }
+
+    /*
+    Writes the trips finished since the last call, and with all_trips those still on their way too, in the order of their ids, then the new light and queue samples.
+    */
+    fn write(&mut self, road: &Road, all_trips: bool) -> std::io::Result<()> {
+        let collector = &road.collector;
+        let mut ids = collector.finished[self.trips_written..].to_vec();
+        self.trips_written = collector.finished.len();
+        if all_trips {
+            ids.extend(
+                collector
+                    .trips
+                    .values()
+                    .filter(|trip| trip.exited.is_none())
+                    .map(|trip| trip.id),
+            );
+        }
+        ids.sort_unstable();
+        for id in ids {
+            let row = TripRow::new(&collector.trips[&id], road.seed);
+            write_row(&mut self.trips, self.format, &TRIP_COLUMNS, &row)?;
+        }
+        for sample in &collector.lights[self.lights_written..] {
+            let row = LightRow::new(sample, road.seed);
+            write_row(&mut self.lights, self.format, &LIGHT_COLUMNS, &row)?;
+        }
+        self.lights_written = collector.lights.len();
+        for sample in &collector.samples[self.samples_written..] {
+            for side in Side::ALL {
+                for direction in Direction::ALL {
+                    let row = QueueRow {
+                        tick: sample.tick,
+                        side,
+                        direction,
+                        queue: sample.queues[side.index()][direction.index()],
+                        throughput: sample.throughput[side.index()][direction.index()],
+                        seed: road.seed,
+                    };
+                    write_row(&mut self.queues, self.format, &QUEUE_COLUMNS, &row)?;
+                }
+            }
+        }
+        self.samples_written = collector.samples.len();
+        Ok(())
+    }
+}
+
+/*
+The rows of the three files, one field per column in the order of the columns. Sides, directions, classes and lights are written by the names they have in scenarios and recordings, and a missing tick is a JSON null or an empty CSV field.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TripRow {
+    pub id: u64,
+    pub side: Side,
+    pub direction: Direction,
+    pub class: VehicleClass,
+    pub spawned_tick: u64,
+    pub arrived_tick: Option<u64>,
+    pub entered_tick: Option<u64>,
+    pub exited_tick: Option<u64>,
+    pub stops: u32,
+    pub delay_ticks: f64,
+    pub seed: u64,
+}
+
+impl TripRow {
+    pub fn new(trip: &TripRecord, seed: u64) -> TripRow {
+        TripRow {
+            id: trip.id,
+            side: trip.side,
+            direction: trip.direction,
+            class: trip.class,
+            spawned_tick: trip.spawned,
+            arrived_tick: trip.arrived,
+            entered_tick: trip.entered,
+            exited_tick: trip.exited,
+            stops: trip.stops,
+            delay_ticks: trip.delay,
+            seed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LightRow {
+    pub tick: u64,
+    pub north: Light,
+    pub north_remaining_ticks: u32,
+    pub east: Light,
+    pub east_remaining_ticks: u32,
+    pub south: Light,
+    pub south_remaining_ticks: u32,
+    pub west: Light,
+    pub west_remaining_ticks: u32,
+    pub seed: u64,
+}
+
+impl LightRow {
+    pub fn new(sample: &LightSample, seed: u64) -> LightRow {
+        let lights = &sample.lights;
+        LightRow {
+            tick: sample.tick,
+            north: lights.north.color,
+            north_remaining_ticks: lights.north.remaining,
+            east: lights.east.color,
+            east_remaining_ticks: lights.east.remaining,
+            south: lights.south.color,
+            south_remaining_ticks: lights.south.remaining,
+            west: lights.west.color,
+            west_remaining_ticks: lights.west.remaining,
+            seed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueueRow {
+    pub tick: u64,
+    pub side: Side,
+    pub direction: Direction,
+    pub queue: usize,
+    pub throughput: usize,
+    pub seed: u64,
+}
+
+/*
+Writes one row: as a JSON object through its Serialize impl, or as the CSV fields of the given columns taken from the same JSON object.
+*/
+fn write_row<T: Serialize>(
+    out: &mut impl Write,
+    format: ExportFormat,
+    columns: &[&str],
+    row: &T,
+) -> std::io::Result<()> {
+    match format {
+        ExportFormat::JsonLines => {
+            serde_json::to_writer(&mut *out, row)?;
+            writeln!(out)
+        }
+        ExportFormat::Csv => {
+            let object = serde_json::to_value(row)?;
+            let fields: Vec<String> = columns
+                .iter()
+                .map(|column| match &object[*column] {
+                    serde_json::Value::Null => String::new(),
+                    serde_json::Value::String(text) => text.clone(),
+                    serde_json::Value::Number(number) => match number.as_u64() {
+                        Some(whole) => whole.to_string(),
+                        None => number.as_f64().unwrap_or_default().to_string(),
+                    },
+                    other => other.to_string(),
+                })
+                .collect();
+            writeln!(out, "{}", fields.join(","))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::scenario::*;
+    use super::*;
+
+    fn output(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("export-{}-{name}", std::process::id()))
+    }
+
+    fn road(ticks: u64) -> Road {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios/am_peak.toml");
+        let mut road = Scenario::load(&path).unwrap().road().unwrap();
+        while road.tick < ticks {
+            road.simulation_loop();
+        }
+        road
+    }
+
+    fn lines(dir: &Path, name: &str) -> Vec<String> {
+        std::fs::read_to_string(dir.join(name))
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn json_lines_hold_the_columns_in_order() {
+        let road = road(3600);
+        let dir = output("jsonl");
+        let mut exporter = Exporter::create(&dir, ExportFormat::JsonLines).unwrap();
+        exporter.finish(&road).unwrap();
+
+        for (name, columns) in [
+            ("trips.jsonl", &TRIP_COLUMNS[..]),
+            ("lights.jsonl", &LIGHT_COLUMNS[..]),
+            ("queues.jsonl", &QUEUE_COLUMNS[..]),
+        ] {
+            let lines = lines(&dir, name);
+            assert!(!lines.is_empty(), "{name} is empty");
+            for line in lines {
+                let object: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(&line).unwrap();
+                assert_eq!(object.len(), columns.len(), "{name}: {line}");
+                assert_eq!(object["seed"], road.seed, "{name}: {line}");
+                let positions: Vec<usize> = columns
+                    .iter()
+                    .map(|column| line.find(&format!("\"{column}\":")).unwrap())
+                    .collect();
+                assert!(positions.is_sorted(), "{name}: {line}");
+            }
+        }
+        let trip: serde_json::Value = serde_json::from_str(&lines(&dir, "trips.jsonl")[0]).unwrap();
+        let side: Side = serde_json::from_value(trip["side"].clone()).unwrap();
+        let class: VehicleClass = serde_json::from_value(trip["class"].clone()).unwrap();
+        let record = &road.collector.trips[&trip["id"].as_u64().unwrap()];
+        assert_eq!((side, class), (record.side, record.class));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn csv_rows_have_a_field_per_column() {
+        let road = road(3600);
+        let dir = output("csv");
+        let mut exporter = Exporter::create(&dir, ExportFormat::Csv).unwrap();
+        exporter.finish(&road).unwrap();
+
+        for (name, columns) in [
+            ("trips.csv", &TRIP_COLUMNS[..]),
+            ("lights.csv", &LIGHT_COLUMNS[..]),
+            ("queues.csv", &QUEUE_COLUMNS[..]),
+        ] {
+            let lines = lines(&dir, name);
+            assert_eq!(lines[0], columns.join(","));
+            assert!(lines.len() > 1, "{name} has no rows");
+            for line in &lines[1..] {
+                assert_eq!(line.split(',').count(), columns.len(), "{name}: {line}");
+            }
+        }
+        let unfinished = road
+            .collector
+            .trips
+            .values()
+            .find(|trip| trip.arrived.is_none())
+            .unwrap();
+        let row = lines(&dir, "trips.csv")
+            .into_iter()
+            .find(|line| line.starts_with(&format!("{},", unfinished.id)))
+            .unwrap();
+        assert!(row.contains(",,,"), "{row}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn streaming_writes_every_trip_once() {
+        let dir = output("stream");
+        let mut exporter = Exporter::create(&dir, ExportFormat::JsonLines).unwrap();
+        let mut road = road(0);
+        while road.tick < 3600 {
+            road.simulation_loop();
+            exporter.write_new(&road).unwrap();
+        }
+        exporter.finish(&road).unwrap();
+
+        let mut ids: Vec<u64> = lines(&dir, "trips.jsonl")
+            .iter()
+            .map(|line| {
+                serde_json::from_str::<serde_json::Value>(line).unwrap()["id"]
+                    .as_u64()
+                    .unwrap()
+            })
+            .collect();
+        ids.sort_unstable();
+        let trips: Vec<u64> = road.collector.trips.keys().copied().collect();
+        assert_eq!(ids, trips);
+        assert_eq!(
+            lines(&dir, "lights.jsonl").len(),
+            road.collector.lights.len()
+        );
+        assert_eq!(
+            lines(&dir, "queues.jsonl").len(),
+            12 * road.collector.samples.len()
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,359 @@
+use super::constants::*;
+use super::controller::*;
+use super::se_base::*;
+
+/*
+One entry of a fixed-time phase sequence:
+
+side: the approach that gets green during this phase
+split: the ticks allotted to the phase, covering its green, amber and all-red clearance
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseSplit {
+    pub side: Side,
+    pub split: u32,
+}
+
+/*
+The SignalPlan struct describes a pre-timed plan the way a signal cabinet does:
+
+cycle: the cycle length in ticks, which must equal the sum of the splits
+phases: the phase sequence, served in order every cycle
+offset: the tick within the master clock at which the first phase of the sequence starts its green
+amber and all_red: the clearance intervals taken out of the end of every split
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalPlan {
+    pub cycle: u32,
+    pub phases: Vec<PhaseSplit>,
+    pub offset: u32,
+    pub amber: u32,
+    pub all_red: u32,
+}
+
+impl SignalPlan {
+    /*
+    Creates a plan with the default amber and all-red intervals. The cycle length is the sum of the splits.
+    */
+    pub fn new(phases: Vec<PhaseSplit>, offset: u32) -> SignalPlan {
+        SignalPlan {
+            cycle: phases.iter().map(|phase| phase.split).sum(),
+            phases,
+            offset,
+            amber: AMBER_TICKS,
+            all_red: ALL_RED_TICKS,
+        }
+    }
+
+    /*
+    Checks that the plan can be run: it has at least one phase, the splits add up to the cycle length, every split leaves some green after its clearance intervals and the offset falls inside the cycle.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        if self.phases.is_empty() {
+            return Err("phases: a plan needs at least one phase".to_string());
+        }
+        let total: u32 = self.phases.iter().map(|phase| phase.split).sum();
+        if total != self.cycle {
+            return Err(format!(
+                "cycle: the splits add up to {} ticks but the cycle is {} ticks",
+                total, self.cycle
+            ));
+        }
+        for (i, phase) in self.phases.iter().enumerate() {
+            if phase.split <= self.amber + self.all_red {
+                return Err(format!(
+                    "phases[{}].split: {} ticks leaves no green after {} ticks of amber and all red",
+                    i,
+                    phase.split,
+                    self.amber + self.all_red
+                ));
+            }
+        }
+        if self.offset >= self.cycle {
+            return Err(format!(
+                "offset: {} ticks is not within the {} tick cycle",
+                self.offset, self.cycle
+            ));
+        }
+        Ok(())
+    }
+
+    /*
+    Returns the position in the cycle at the given tick of the master clock, with 0 being the start of the first phase.
+    */
+    pub fn local_time(&self, tick: u64) -> u32 {
+        let cycle = self.cycle as u64;
+        ((tick % cycle + cycle - self.offset as u64 % cycle) % cycle) as u32
+    }
+
+    /*
+    Returns the light state of every approach at the given tick. The served approach is green or amber with the ticks left in that interval.
+    A red approach carries the ticks until its next phase starts, or 0 when it is not part of the sequence.
+    */
+    pub fn state_at(&self, tick: u64) -> SignalState {
+        let local = self.local_time(tick);
+        let mut state = SignalState::all_red();
+        let mut start = 0;
+        for phase in &self.phases {
+            if local >= start && local < start + phase.split {
+                let into = local - start;
+                let green = phase.split - self.amber - self.all_red;
+                if into < green {
+                    *state.light_mut(phase.side) = TrafficLight {
+                        color: Light::Green,
+                        remaining: green - into,
+                    };
+                } else if into < green + self.amber {
+                    *state.light_mut(phase.side) = TrafficLight {
+                        color: Light::Amber,
+                        remaining: green + self.amber - into,
+                    };
+                }
+            }
+            start += phase.split;
+        }
+        for side in Side::ALL {
+            if state.light(side).color == Light::Red {
+                state.light_mut(side).remaining = self.ticks_until_green(side, local);
+            }
+        }
+        state
+    }
+
+    fn ticks_until_green(&self, side: Side, local: u32) -> u32 {
+        let mut start = 0;
+        let mut best: Option<u32> = None;
+        for phase in &self.phases {
+            if phase.side == side {
+                let wait = (start + self.cycle - local) % self.cycle;
+                best = Some(best.map_or(wait, |b| b.min(wait)));
+            }
+            start += phase.split;
+        }
+        best.unwrap_or(0)
+    }
+}
+
+/*
+A plan together with the tick of the master clock from which it should be running.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScheduledPlan {
+    pub start: u64,
+    pub plan: SignalPlan,
+}
+
+/*
+The FixedTime controller runs pre-timed plans and ignores the queues entirely.
+
+It holds a time-of-day schedule of plans, sorted by start tick. When a new plan becomes due, the running plan first finishes its current cycle. The new plan then dwells in the green of its first phase until its own cycle lines up with its offset, so no phase is ever cut short during the transition.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FixedTime {
+    pub schedule: Vec<ScheduledPlan>,
+    pub active: usize,
+    pub pending: Option<usize>,
+    pub dwell_until: Option<u64>,
+}
+
+impl FixedTime {
+    /*
+    Creates a controller that runs a single plan for the whole simulation, or reports why the plan cannot be run.
+    */
+    pub fn new(plan: SignalPlan) -> Result<FixedTime, String> {
+        FixedTime::with_schedule(vec![ScheduledPlan { start: 0, plan }])
+    }
+
+    /*
+    Creates a controller from a time-of-day schedule. The plans are sorted by start tick and the earliest one runs until the first transition.
+    The schedule is checked with validate first, as a plan whose splits leave no green or whose cycle is empty cannot be run.
+    */
+    pub fn with_schedule(mut schedule: Vec<ScheduledPlan>) -> Result<FixedTime, String> {
+        schedule.sort_by_key(|entry| entry.start);
+        let controller = FixedTime {
+            schedule,
+            active: 0,
+            pending: None,
+            dwell_until: None,
+        };
+        controller.validate()?;
+        Ok(controller)
+    }
+
+    /*
+    Checks every plan of the schedule and reports the first problem found, prefixed with the position of the plan.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        if self.schedule.is_empty() {
+            return Err("schedule: at least one plan is required".to_string());
+        }
+        for (i, entry) in self.schedule.iter().enumerate() {
+            entry
+                .plan
+                .validate()
+                .map_err(|err| format!("schedule[{}].{}", i, err))?;
+        }
+        Ok(())
+    }
+
+    pub fn plan(&self) -> &SignalPlan {
+        &self.schedule[self.active].plan
+    }
+
+    fn due(&self, tick: u64) -> usize {
+        self.schedule
+            .iter()
+            .rposition(|entry| entry.start <= tick)
+            .unwrap_or(0)
+    }
+}
+
+impl SignalController for FixedTime {
+    fn update(&mut self, observation: &Observation) -> SignalState {
+        let tick = observation.elapsed;
+        let due = self.due(tick);
+        if due != self.active && self.pending.is_none() {
+            self.pending = Some(due);
+        }
+        if let Some(next) = self.pending
+            && self.plan().local_time(tick) == 0
+        {
+            self.active = next;
+            self.pending = None;
+            let local = self.plan().local_time(tick);
+            if local != 0 {
+                self.dwell_until = Some(tick + (self.plan().cycle - local) as u64);
+            }
+        }
+        match self.dwell_until {
+            Some(end) if tick < end => {
+                let plan = self.plan();
+                let first = &plan.phases[0];
+                let green = first.split - plan.amber - plan.all_red;
+                let mut state = SignalState::all_red();
+                *state.light_mut(first.side) = TrafficLight {
+                    color: Light::Green,
+                    remaining: (end - tick) as u32 + green,
+                };
+                state
+            }
+            _ => {
+                self.dwell_until = None;
+                self.plan().state_at(tick)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(splits: &[(Side, u32)], offset: u32) -> SignalPlan {
+        let phases = splits
+            .iter()
+            .map(|&(side, split)| PhaseSplit { side, split })
+            .collect();
+        SignalPlan::new(phases, offset)
+    }
+
+    fn observation(elapsed: u64) -> Observation {
+        Observation {
+            queues: [0; 4],
+            occupancy: 0,
+            elapsed,
+            lights: SignalState::all_red(),
+            pedestrians: [0; 4],
+        }
+    }
+
+    #[test]
+    fn the_plan_gives_each_phase_its_split() {
+        let plan = plan(&[(Side::FromNorth, 600), (Side::FromEast, 600)], 0);
+        let green = 600 - AMBER_TICKS - ALL_RED_TICKS;
+        let state = plan.state_at(0);
+        assert_eq!(state.north.color, Light::Green);
+        assert_eq!(state.north.remaining, green);
+        assert_eq!(state.east.color, Light::Red);
+        assert_eq!(state.east.remaining, 600);
+        assert_eq!(state.south.remaining, 0);
+        let state = plan.state_at(green as u64);
+        assert_eq!(state.north.color, Light::Amber);
+        assert_eq!(state.north.remaining, AMBER_TICKS);
+        let state = plan.state_at((green + AMBER_TICKS) as u64);
+        assert!(!state.any_green());
+        assert_eq!(state.east.remaining, ALL_RED_TICKS);
+        assert_eq!(plan.state_at(600).east.color, Light::Green);
+        assert_eq!(plan.state_at(1200), plan.state_at(0));
+
+        let shifted = SignalPlan {
+            offset: 100,
+            ..plan.clone()
+        };
+        for tick in [0, 250, 599, 1000] {
+            assert_eq!(shifted.state_at(tick + 100), plan.state_at(tick));
+        }
+    }
+
+    #[test]
+    fn a_new_plan_waits_for_the_cycle_to_end_and_lines_up_with_its_offset() {
+        let first = plan(&[(Side::FromNorth, 600), (Side::FromEast, 600)], 0);
+        let second = plan(&[(Side::FromWest, 400), (Side::FromSouth, 400)], 300);
+        let mut controller = FixedTime::with_schedule(vec![
+            ScheduledPlan {
+                start: 1000,
+                plan: second.clone(),
+            },
+            ScheduledPlan {
+                start: 0,
+                plan: first.clone(),
+            },
+        ])
+        .unwrap();
+        for tick in 0..1200 {
+            assert_eq!(controller.update(&observation(tick)), first.state_at(tick));
+        }
+        for tick in 1200..1900 {
+            let state = controller.update(&observation(tick));
+            assert_eq!(controller.active, 1);
+            assert_eq!(state.west.color, Light::Green);
+            assert!(state.west.remaining > 400 - AMBER_TICKS - ALL_RED_TICKS);
+        }
+        for tick in 1900..3000 {
+            assert_eq!(controller.update(&observation(tick)), second.state_at(tick));
+        }
+    }
+
+    #[test]
+    fn plans_that_cannot_run_are_rejected() {
+        let clearance = AMBER_TICKS + ALL_RED_TICKS;
+        let errors = [
+            (plan(&[], 0), "schedule[0].phases: "),
+            (
+                plan(&[(Side::FromNorth, 600), (Side::FromEast, clearance)], 0),
+                "schedule[0].phases[1].split: ",
+            ),
+            (
+                plan(&[(Side::FromNorth, 600), (Side::FromEast, 600)], 1200),
+                "schedule[0].offset: ",
+            ),
+            (
+                SignalPlan {
+                    cycle: 0,
+                    ..plan(&[(Side::FromNorth, 600)], 0)
+                },
+                "schedule[0].cycle: ",
+            ),
+        ];
+        for (plan, error) in errors {
+            let message = FixedTime::new(plan).unwrap_err();
+            assert!(message.starts_with(error), "{}", message);
+        }
+        assert_eq!(
+            FixedTime::with_schedule(vec![]).unwrap_err(),
+            "schedule: at least one plan is required"
+        );
+        let runnable = plan(&[(Side::FromNorth, clearance + 1)], 0);
+        assert!(FixedTime::new(runnable).is_ok());
+    }
+}
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+
+use super::constants::*;
+use super::se_base::*;
+
+/*
+The Geometry struct describes the layout of the intersection, all in pixels:
+
+width and height: the size of the canvas, with the intersection at its center
+lane_width: the width of a lane
+lanes: the number of lanes each leg has in each direction
+setback: how far the stop line is set back from the intersection box, which is the width of the crosswalk laid between them
+approach: the length of an approach, from the end of the road to the stop line
+
+Lanes are numbered from the middle of the road outwards, so lane 0 is the one next to the center line and lanes - 1 the one along the curb. The intersection box is where the two roads cross, 2 * lanes lanes wide on each side.
+
+The default is the original single-lane intersection in the middle of an 800 by 800 canvas, its roads running to the edges of the canvas.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geometry {
+    pub width: i32,
+    pub height: i32,
+    pub lane_width: i32,
+    pub lanes: i32,
+    pub setback: i32,
+    pub approach: i32,
+}
+
+impl Default for Geometry {
+    fn default() -> Geometry {
+        Geometry {
+            width: OUTPUT_WIDTH,
+            height: OUTPUT_HEIGHT,
+            lane_width: CAR_WIDTH,
+            lanes: 1,
+            setback: CROSSWALK_WIDTH,
+            approach: OUTPUT_WIDTH / 2 - CAR_WIDTH - CROSSWALK_WIDTH,
+        }
+    }
+}
+
+impl Geometry {
+    /*
+    Checks that the intersection can be driven: a lane must hold the widest vehicle, the crosswalk a pedestrian, and the approach a vehicle arriving in front of its stop line, and the roads must fit on the canvas.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        for (field, value, least) in [
+            ("width", self.width, 1),
+            ("height", self.height, 1),
+            ("lane_width", self.lane_width, CAR_WIDTH),
+            ("lanes", self.lanes, 1),
+            ("setback", self.setback, PEDESTRIAN_SIZE),
+            ("approach", self.approach, 2 * self.lane_width),
+        ] {
+            if value < least {
+                return Err(format!(
+                    "{}: {} is below the least of {}",
+                    field, value, least
+                ));
+            }
+        }
+        let reach = self.reach();
+        if 2 * reach > self.width.min(self.height) {
+            return Err(format!(
+                "approach: the roads reach {} pixels from the center, past the edge of a {} by {} canvas",
+                reach, self.width, self.height
+            ));
+        }
+        Ok(())
+    }
+
+    /*
+    Returns the center of the intersection on the canvas.
+    */
+    pub fn center(&self) -> (i32, i32) {
+        (self.width / 2, self.height / 2)
+    }
+
+    /*
+    Returns the distance from the center to the edges of the intersection box, the width of one direction of a road.
+    */
+    pub fn half_box(&self) -> i32 {
+        self.lanes * self.lane_width
+    }
+
+    /*
+    Returns the distance from the center to the stop lines.
+    */
+    pub fn stop_line(&self) -> i32 {
+        self.half_box() + self.setback
+    }
+
+    /*
+    Returns the distance from the center to the ends of the roads.
+    */
+    pub fn reach(&self) -> i32 {
+        self.stop_line() + self.approach
+    }
+
+    /*
+    Returns the rectangle (x, y, width, height) of the intersection box.
+    */
+    pub fn intersection_box(&self) -> (i32, i32, i32, i32) {
+        let (x, y) = self.center();
+        let half = self.half_box();
+        (x - half, y - half, 2 * half, 2 * half)
+    }
+
+    /*
+    Returns the rectangle (x, y, width, height) of the crosswalk on the given leg. It lies across the whole road, between the stop line and the intersection box.
+    */
+    pub fn crosswalk(&self, leg: Side) -> (i32, i32, i32, i32) {
+        let (left, top, size, _) = self.intersection_box();
+        let setback = self.setback;
+        match leg {
+            Side::FromNorth => (left, top - setback, size, setback),
+            Side::FromSouth => (left, top + size, size, setback),
+            Side::FromEast => (left - setback, top, setback, size),
+            Side::FromWest => (left + size, top, setback, size),
+        }
+    }
+
+    /*
+    Returns how far a pedestrian walks from curb to curb: across the road and CROSSWALK_CURB on either side of it.
+    */
+    pub fn crossing_length(&self) -> i32 {
+        2 * self.half_box() + 2 * CROSSWALK_CURB
+    }
+
+    /*
+    Returns the number of ticks a pedestrian at PEDESTRIAN_SPEED takes to cross the road, which is how long before the end of a green the crosswalks parallel to it stop showing Walk.
+    */
+    pub fn clearance_ticks(&self) -> u32 {
+        ((2 * self.half_box()) as f64 / PEDESTRIAN_SPEED * TICKS_PER_SECOND as f64) as u32
+    }
+
+    /*
+    Returns the lane a movement in the given direction drives in: left turns keep to the center line, right turns to the curb, and straight movements take the middle lane, the curb lane of a road of two lanes. A movement leaves the intersection in the lane of the same number on its exit leg, so a turn ends on the same side of the road as it started.
+    */
+    pub fn lane(&self, direction: Direction) -> i32 {
+        match direction {
+            Direction::Left => 0,
+            Direction::Straight => self.lanes / 2,
+            Direction::Right => self.lanes - 1,
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use super::constants::*;
+use super::preemption::*;
+use super::road::*;
+use super::se_base::*;
+
+/*
+Returns the short name of an approach used on screen.
+*/
+pub fn side_label(side: Side) -> &'static str {
+    match side {
+        Side::FromNorth => "N",
+        Side::FromEast => "E",
+        Side::FromSouth => "S",
+        Side::FromWest => "W",
+    }
+}
+
+/*
+Formats a number of ticks as simulated seconds with one decimal, or a dash when the time is not known.
+*/
+fn seconds(ticks: u32) -> String {
+    if ticks == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}S", ticks as f64 * SIMULATION_STEP)
+}
+
+/*
+Describes who holds the intersection: the reservations in reservation mode, the stage of a running emergency vehicle preemption, or else the approaches that have green, or amber, with the time their light has left.
+*/
+fn phase_line(road: &Road) -> String {
+    if let Some(manager) = &road.manager {
+        return format!(
+            "RESERVATIONS {} HELD {} GRANTED",
+            manager.reservations.len(),
+            manager.granted
+        );
+    }
+    let preemption = match road.preemption.stage {
+        PreemptionStage::Idle => String::new(),
+        stage => format!(
+            "PREEMPTION {} {} ",
+            format!("{:?}", stage).to_uppercase(),
+            side_label(road.preemption.side)
+        ),
+    };
+    for color in [Light::Green, Light::Amber] {
+        let sides: Vec<Side> = Side::ALL
+            .into_iter()
+            .filter(|side| road.lights(*side).color == color)
+            .collect();
+        if let Some(first) = sides.first() {
+            let names: Vec<&str> = sides.iter().map(|side| side_label(*side)).collect();
+            return format!(
+                "{}PHASE {} {} {}",
+                preemption,
+                names.join("+"),
+                format!("{:?}", color).to_uppercase(),
+                seconds(road.lights(*first).remaining)
+            );
+        }
+    }
+    format!("{}PHASE ALL RED", preemption)
+}
+
+/*
+Returns the lines of the heads-up display drawn over the intersection, from top to bottom:
+
+the simulation clock in minutes and seconds, with the tick
+the current phase and the time it has left, see phase_line
+the number of vehicles in each cars_before_stop_* queue
+the vehicles that have left the intersection so far and their average delay in seconds
+*/
+pub fn hud_lines(road: &Road) -> Vec<String> {
+    let tenths = road.tick * 10 / TICKS_PER_SECOND as u64;
+    let queues: Vec<String> = Side::ALL
+        .into_iter()
+        .map(|side| format!("{} {}", side_label(side), road.cars_before_stop(side).len()))
+        .collect();
+    let (through, delay) = road
+        .collector
+        .trips
+        .values()
+        .filter(|trip| trip.exited.is_some())
+        .fold((0, 0.0), |(count, delay), trip| {
+            (count + 1, delay + trip.delay)
+        });
+    let average = if through > 0 {
+        delay / through as f64 * SIMULATION_STEP
+    } else {
+        0.0
+    };
+    vec![
+        format!(
+            "TIME {:02}:{:02}.{} TICK {}",
+            tenths / 600,
+            tenths % 600 / 10,
+            tenths % 10,
+            road.tick
+        ),
+        phase_line(road),
+        format!("QUEUES {}", queues.join("  ")),
+        format!("THROUGH {} AVG DELAY {:.1}S", through, average),
+    ]
+}
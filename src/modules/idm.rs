@@ -0,0 +1,113 @@
+use super::constants::*;
+
+/*
+The Idm struct holds the parameters of the Intelligent Driver Model, the car-following model that sets every car's acceleration. Distances are in pixels and times in simulated seconds:
+
+desired_speed: the speed the car drives at on a free road
+max_acceleration: the acceleration the car uses to get up to speed
+comfortable_deceleration: the braking the car aims for when it slows down for a leader or a stop line
+time_headway: the time gap the car keeps to its leader
+min_gap: the bumper to bumper gap the car keeps when standing still
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Idm {
+    pub desired_speed: f64,
+    pub max_acceleration: f64,
+    pub comfortable_deceleration: f64,
+    pub time_headway: f64,
+    pub min_gap: f64,
+}
+
+impl Idm {
+    /*
+    Creates a model with the default parameters and the given desired speed.
+    */
+    pub fn new(desired_speed: f64) -> Idm {
+        Idm {
+            desired_speed,
+            max_acceleration: IDM_MAX_ACCELERATION,
+            comfortable_deceleration: IDM_COMFORTABLE_DECELERATION,
+            time_headway: IDM_TIME_HEADWAY,
+            min_gap: IDM_MIN_GAP,
+        }
+    }
+
+    /*
+    Returns the acceleration of a car driving at the given speed.
+    leader is the bumper to bumper gap to whatever is ahead and the speed it moves at, or None when the road ahead is free.
+    */
+    pub fn acceleration(&self, speed: f64, leader: Option<(f64, f64)>) -> f64 {
+        let free = 1.0 - (speed / self.desired_speed).powi(IDM_DELTA);
+        let interaction = match leader {
+            Some((gap, leader_speed)) => {
+                let approach = speed - leader_speed;
+                let desired_gap = self.min_gap
+                    + (speed * self.time_headway
+                        + speed * approach
+                            / (2.0
+                                * (self.max_acceleration * self.comfortable_deceleration).sqrt()))
+                    .max(0.0);
+                (desired_gap / gap.max(0.1)).powi(2)
+            }
+            None => 0.0,
+        };
+        self.max_acceleration * (free - interaction)
+    }
+
+    /*
+    Returns the distance a car at this speed needs to come to a halt at the comfortable deceleration.
+    */
+    pub fn stopping_distance(&self, speed: f64) -> f64 {
+        speed * speed / (2.0 * self.comfortable_deceleration)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_free_road_brings_the_car_up_to_its_desired_speed() {
+        let idm = Idm::new(150.0);
+        assert_eq!(idm.acceleration(0.0, None), idm.max_acceleration);
+        assert_eq!(idm.acceleration(150.0, None), 0.0);
+        assert!(idm.acceleration(200.0, None) < 0.0);
+        let mut speed = 0.0;
+        for _ in 0..60 * TICKS_PER_SECOND {
+            speed += idm.acceleration(speed, None) * SIMULATION_STEP;
+        }
+        assert!((speed - 150.0).abs() < 1.0, "{}", speed);
+    }
+
+    #[test]
+    fn a_car_comes_to_a_halt_behind_a_standing_leader() {
+        let idm = Idm::new(150.0);
+        assert!(idm.acceleration(150.0, Some((30.0, 0.0))) < -idm.comfortable_deceleration);
+        assert!(
+            idm.acceleration(150.0, Some((30.0, 150.0)))
+                > idm.acceleration(150.0, Some((30.0, 0.0)))
+        );
+        let (mut speed, mut gap) = (150.0, 400.0);
+        for _ in 0..60 * TICKS_PER_SECOND {
+            speed = (speed + idm.acceleration(speed, Some((gap, 0.0))) * SIMULATION_STEP).max(0.0);
+            gap -= speed * SIMULATION_STEP;
+            assert!(gap > 0.0);
+        }
+        assert!(speed < 1.0, "{}", speed);
+        assert!((gap - idm.min_gap).abs() < 1.0, "{}", gap);
+    }
+
+    #[test]
+    fn the_stopping_distance_grows_with_the_square_of_the_speed() {
+        let idm = Idm::new(150.0);
+        assert_eq!(idm.stopping_distance(0.0), 0.0);
+        assert_eq!(
+            idm.stopping_distance(100.0),
+            4.0 * idm.stopping_distance(50.0)
+        );
+        assert_eq!(
+            idm.stopping_distance(idm.comfortable_deceleration),
+            idm.comfortable_deceleration / 2.0
+        );
+    }
+}
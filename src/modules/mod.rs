@@ -1,12 +1,57 @@
 extern crate rand;
-extern crate sdl2;
 
+pub mod capture;
 pub mod car;
+pub mod clock;
+pub mod conflict;
 pub mod constants;
+pub mod controller;
+pub mod demand;
+pub mod draw;
+pub mod export;
+pub mod fixed_time;
+pub mod font;
+pub mod geometry;
+pub mod hud;
+pub mod idm;
+pub mod pedestrian;
+pub mod preemption;
+pub mod recording;
+pub mod reservation;
 pub mod road;
+pub mod scenario;
 pub mod se_base;
+pub mod speeds;
+pub mod stats;
+pub mod timing;
+pub mod trajectory;
+pub mod turning;
+pub mod vehicle;
 
+pub use capture::*;
 pub use car::*;
+pub use clock::*;
+pub use conflict::*;
 pub use constants::*;
+pub use controller::*;
+pub use demand::*;
+pub use draw::*;
+pub use export::*;
+pub use fixed_time::*;
+pub use font::*;
+pub use geometry::*;
+pub use hud::*;
+pub use idm::*;
+pub use pedestrian::*;
+pub use preemption::*;
+pub use recording::*;
+pub use reservation::*;
 pub use road::*;
+pub use scenario::*;
 pub use se_base::*;
+pub use speeds::*;
+pub use stats::*;
+pub use timing::*;
+pub use trajectory::*;
+pub use turning::*;
+pub use vehicle::*;
@@ -0,0 +1,203 @@
+use super::constants::*;
+use super::controller::*;
+use super::geometry::*;
+use super::se_base::*;
+
+/*
+The pedestrian indication shown at a crosswalk:
+
+Walk: pedestrians may start crossing
+FlashingDontWalk: pedestrians must not start crossing, those already crossing finish
+DontWalk: pedestrians must not cross
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PedestrianLight {
+    Walk,
+    FlashingDontWalk,
+    DontWalk,
+}
+
+impl PedestrianLight {
+    /*
+    Derives the indication of the crosswalk on the given leg from the vehicle lights.
+
+    A crosswalk walks alongside the phases that are parallel to it, the two approaches on the other legs, whose straight movements never cross it. It shows Walk during their green, then FlashingDontWalk during the last clearance ticks of that green, the time it takes to cross (Geometry::clearance_ticks), and through the amber, so that a pedestrian who starts on Walk has time to finish. It shows DontWalk otherwise.
+    A green whose end is not known yet (remaining is 0) keeps showing Walk.
+    */
+    pub fn for_leg(leg: Side, lights: &SignalState, clearance: u32) -> PedestrianLight {
+        let mut indication = PedestrianLight::DontWalk;
+        for side in Side::ALL {
+            if side == leg || side == leg.opposite() {
+                continue;
+            }
+            let light = lights.light(side);
+            match light.color {
+                Light::Green if light.remaining == 0 || light.remaining > clearance => {
+                    return PedestrianLight::Walk;
+                }
+                Light::Green | Light::Amber => indication = PedestrianLight::FlashingDontWalk,
+                Light::Red => {}
+            }
+        }
+        indication
+    }
+}
+
+/*
+The Pedestrian struct represents a pedestrian using one of the crosswalks:
+
+id: a number identifying the pedestrian, assigned by Road::spawn_pedestrian
+leg: the leg whose crosswalk the pedestrian crosses
+forward: whether the pedestrian walks from the top or left curb (true) or from the bottom or right curb (false)
+position: the distance walked from the waiting spot on the starting curb, up to Geometry::crossing_length on the far curb
+crossing: whether the pedestrian has stepped off the curb
+arrived: the tick the pedestrian arrived at the crosswalk
+started: the tick the pedestrian started crossing
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pedestrian {
+    pub id: u64,
+    pub leg: Side,
+    pub forward: bool,
+    pub position: f64,
+    pub crossing: bool,
+    pub arrived: u64,
+    pub started: Option<u64>,
+}
+
+impl Pedestrian {
+    pub fn new(leg: Side, forward: bool, arrived: u64) -> Pedestrian {
+        Pedestrian {
+            id: 0,
+            leg,
+            forward,
+            position: 0.0,
+            crossing: false,
+            arrived,
+            started: None,
+        }
+    }
+
+    /*
+    Returns the number of ticks the pedestrian waited at the curb before crossing, counting up to now while it is still waiting.
+    */
+    pub fn wait(&self, now: u64) -> u64 {
+        self.started.unwrap_or(now) - self.arrived
+    }
+
+    /*
+    Returns whether the pedestrian has reached the far curb of its crosswalk in the given intersection.
+    */
+    pub fn has_crossed(&self, geometry: &Geometry) -> bool {
+        self.position >= geometry.crossing_length() as f64
+    }
+
+    /*
+    Returns the square (x, y, size, size) the pedestrian covers on the canvas, centered across its crosswalk.
+    */
+    pub fn footprint(&self, geometry: &Geometry) -> (i32, i32, i32, i32) {
+        let (x, y, width, height) = geometry.crosswalk(self.leg);
+        let walked = self.position as i32 - CROSSWALK_CURB;
+        let half = PEDESTRIAN_SIZE / 2;
+        match self.leg {
+            Side::FromNorth | Side::FromSouth => {
+                let along = if self.forward {
+                    x + walked
+                } else {
+                    x + width - walked
+                };
+                (
+                    along - half,
+                    y + height / 2 - half,
+                    PEDESTRIAN_SIZE,
+                    PEDESTRIAN_SIZE,
+                )
+            }
+            Side::FromEast | Side::FromWest => {
+                let along = if self.forward {
+                    y + walked
+                } else {
+                    y + height - walked
+                };
+                (
+                    x + width / 2 - half,
+                    along - half,
+                    PEDESTRIAN_SIZE,
+                    PEDESTRIAN_SIZE,
+                )
+            }
+        }
+    }
+
+    /*
+    Moves the pedestrian for one tick. A waiting pedestrian steps off the curb when the indication is Walk and may_start allows it, and a crossing pedestrian walks on at PEDESTRIAN_SPEED, in pixels per second, for one SIMULATION_STEP whatever the indication.
+    */
+    pub fn walk(&mut self, indication: PedestrianLight, may_start: bool, now: u64) {
+        if !self.crossing && indication == PedestrianLight::Walk && may_start {
+            self.crossing = true;
+            self.started = Some(now);
+        }
+        if self.crossing {
+            self.position += PEDESTRIAN_SPEED * SIMULATION_STEP;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lights(side: Side, color: Light, remaining: u32) -> SignalState {
+        let mut lights = SignalState::all_red();
+        *lights.light_mut(side) = TrafficLight { color, remaining };
+        lights
+    }
+
+    #[test]
+    fn a_crosswalk_walks_with_the_phases_parallel_to_it() {
+        let clearance = 100;
+        let walk = |leg, lights: &SignalState| PedestrianLight::for_leg(leg, lights, clearance);
+        let green = lights(Side::FromEast, Light::Green, 600);
+        assert_eq!(walk(Side::FromNorth, &green), PedestrianLight::Walk);
+        assert_eq!(walk(Side::FromSouth, &green), PedestrianLight::Walk);
+        assert_eq!(walk(Side::FromEast, &green), PedestrianLight::DontWalk);
+        assert_eq!(walk(Side::FromWest, &green), PedestrianLight::DontWalk);
+
+        let ending = lights(Side::FromEast, Light::Green, clearance);
+        assert_eq!(
+            walk(Side::FromNorth, &ending),
+            PedestrianLight::FlashingDontWalk
+        );
+        let amber = lights(Side::FromWest, Light::Amber, 50);
+        assert_eq!(
+            walk(Side::FromNorth, &amber),
+            PedestrianLight::FlashingDontWalk
+        );
+        let open = lights(Side::FromWest, Light::Green, 0);
+        assert_eq!(walk(Side::FromNorth, &open), PedestrianLight::Walk);
+        assert_eq!(
+            walk(Side::FromNorth, &SignalState::all_red()),
+            PedestrianLight::DontWalk
+        );
+    }
+
+    #[test]
+    fn pedestrians_start_on_walk_and_finish_whatever_the_indication() {
+        let geometry = Geometry::default();
+        let mut pedestrian = Pedestrian::new(Side::FromNorth, true, 10);
+        pedestrian.walk(PedestrianLight::FlashingDontWalk, true, 20);
+        pedestrian.walk(PedestrianLight::Walk, false, 30);
+        assert!(!pedestrian.crossing);
+        assert_eq!(pedestrian.wait(40), 30);
+        pedestrian.walk(PedestrianLight::Walk, true, 40);
+        assert!(pedestrian.crossing);
+        assert_eq!(pedestrian.wait(100), 30);
+        let mut now = 41;
+        while !pedestrian.has_crossed(&geometry) {
+            pedestrian.walk(PedestrianLight::DontWalk, false, now);
+            now += 1;
+        }
+        let expected = geometry.crossing_length() as f64 / PEDESTRIAN_SPEED;
+        assert!(((now - 40) as f64 * SIMULATION_STEP - expected).abs() <= SIMULATION_STEP);
+    }
+}
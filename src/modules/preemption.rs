@@ -0,0 +1,246 @@
+use super::controller::*;
+use super::se_base::*;
+use super::timing::*;
+
+/*
+The stage a preemption is in:
+
+Idle: normal operation, the signal controller is in charge
+Clearing: the phase that was running is being ended safely with its amber and all-red intervals
+Serving: the emergency vehicle's approach is green and every other approach is red
+Recovering: the emergency vehicle has entered the intersection and its approach runs amber and all red before control goes back to the signal controller
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreemptionStage {
+    Idle,
+    Clearing,
+    Serving,
+    Recovering,
+}
+
+/*
+One line of the preemption log: what happened, at which tick, for which approach and emergency vehicle, and how long the queues on all approaches were at that moment.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreemptionEvent {
+    pub tick: u64,
+    pub stage: PreemptionStage,
+    pub side: Side,
+    pub car: u64,
+    pub queues: [usize; 4],
+}
+
+/*
+The Preemption struct overrides the signal controller while an emergency vehicle goes through the intersection.
+
+When an emergency vehicle is detected within PREEMPTION_DETECTION_DISTANCE of its stop line, any other approach that is green or amber is ended safely with an amber and an all-red interval, as long as those of the signal timing in use, and the emergency vehicle's approach is then given green. The cars queued ahead of the emergency vehicle drive through that green and clear the way for it. Once the emergency vehicle has entered the intersection its approach runs amber and all red, and the signal controller takes over again.
+
+The cars queued ahead of it in the curb lane pull over to let it by where they can, see Road::pull_aside. Cars on the other approaches are held by their red lights.
+
+Every change of stage is appended to log.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preemption {
+    pub stage: PreemptionStage,
+    pub side: Side,
+    pub car: u64,
+    pub elapsed: u32,
+    pub amber: u32,
+    pub all_red: u32,
+    pub lights: SignalState,
+    pub log: Vec<PreemptionEvent>,
+}
+
+impl Default for Preemption {
+    fn default() -> Preemption {
+        Preemption::new(&SignalTiming::default())
+    }
+}
+
+impl Preemption {
+    /*
+    Creates an idle Preemption that clears and recovers with the amber and all-red intervals of the given timing.
+    */
+    pub fn new(timing: &SignalTiming) -> Preemption {
+        Preemption {
+            stage: PreemptionStage::Idle,
+            side: Side::FromNorth,
+            car: 0,
+            elapsed: 0,
+            amber: timing.amber,
+            all_red: timing.all_red,
+            lights: SignalState::all_red(),
+            log: vec![],
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.stage != PreemptionStage::Idle
+    }
+
+    /*
+    Starts a preemption for the emergency vehicle car coming from side. lights is the state that was showing when the vehicle was detected. Ignored while another preemption is running.
+    */
+    pub fn start(&mut self, side: Side, car: u64, lights: &SignalState, observation: &Observation) {
+        if self.is_active() {
+            return;
+        }
+        self.side = side;
+        self.car = car;
+        self.lights = lights.clone();
+        self.enter(PreemptionStage::Clearing, observation);
+    }
+
+    /*
+    Tells the preemption whether its emergency vehicle is still waiting to enter the intersection, and advances it by one tick.
+    */
+    pub fn update(&mut self, vehicle_waiting: bool, observation: &Observation) {
+        self.elapsed += 1;
+        match self.stage {
+            PreemptionStage::Idle => {}
+            PreemptionStage::Clearing => {
+                let conflicting = Side::ALL
+                    .iter()
+                    .any(|side| *side != self.side && self.lights.light(*side).color != Light::Red);
+                if !conflicting || self.elapsed >= self.amber + self.all_red {
+                    self.enter(PreemptionStage::Serving, observation);
+                }
+            }
+            PreemptionStage::Serving => {
+                if !vehicle_waiting {
+                    self.enter(PreemptionStage::Recovering, observation);
+                }
+            }
+            PreemptionStage::Recovering => {
+                if self.elapsed >= self.amber + self.all_red {
+                    self.enter(PreemptionStage::Idle, observation);
+                }
+            }
+        }
+    }
+
+    /*
+    Returns the lights to show during the preemption.
+    While clearing, the approaches that were not red show amber and then red, and the emergency vehicle's approach keeps green if it already had it. While serving, only that approach is green. While recovering, it shows amber and then red.
+    */
+    pub fn state(&self) -> SignalState {
+        let mut state = SignalState::all_red();
+        match self.stage {
+            PreemptionStage::Idle => return self.lights.clone(),
+            PreemptionStage::Clearing => {
+                for side in Side::ALL {
+                    let was = self.lights.light(side).color;
+                    if side == self.side && was == Light::Green {
+                        *state.light_mut(side) = TrafficLight::new(Light::Green);
+                    } else if was != Light::Red && self.elapsed < self.amber {
+                        *state.light_mut(side) = TrafficLight {
+                            color: Light::Amber,
+                            remaining: self.amber - self.elapsed,
+                        };
+                    }
+                }
+            }
+            PreemptionStage::Serving => {
+                *state.light_mut(self.side) = TrafficLight::new(Light::Green);
+            }
+            PreemptionStage::Recovering => {
+                if self.elapsed < self.amber {
+                    *state.light_mut(self.side) = TrafficLight {
+                        color: Light::Amber,
+                        remaining: self.amber - self.elapsed,
+                    };
+                }
+            }
+        }
+        state
+    }
+
+    fn enter(&mut self, stage: PreemptionStage, observation: &Observation) {
+        self.stage = stage;
+        self.elapsed = 0;
+        self.log.push(PreemptionEvent {
+            tick: observation.elapsed,
+            stage,
+            side: self.side,
+            car: self.car,
+            queues: observation.queues,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::constants::*;
+    use super::super::scenario::*;
+    use super::*;
+
+    fn observation() -> Observation {
+        Observation {
+            queues: [0; 4],
+            occupancy: 0,
+            elapsed: 0,
+            lights: SignalState::all_red(),
+            pedestrians: [0; 4],
+        }
+    }
+
+    #[test]
+    fn clearing_runs_the_configured_amber_and_all_red() {
+        let timing = SignalTiming {
+            amber: 240,
+            all_red: 90,
+            ..SignalTiming::default()
+        };
+        let mut preemption = Preemption::new(&timing);
+        let mut lights = SignalState::all_red();
+        *lights.light_mut(Side::FromEast) = TrafficLight::new(Light::Green);
+        preemption.start(Side::FromNorth, 7, &lights, &observation());
+
+        let mut ambers = 0;
+        let mut ticks = 0;
+        while preemption.stage == PreemptionStage::Clearing {
+            if preemption.state().east.color == Light::Amber {
+                ambers += 1;
+            }
+            preemption.update(true, &observation());
+            ticks += 1;
+        }
+        assert_eq!(ambers, 240);
+        assert_eq!(ticks, 240 + 90);
+        assert_eq!(preemption.stage, PreemptionStage::Serving);
+        assert_eq!(preemption.state().north.color, Light::Green);
+        assert_eq!(preemption.state().east.color, Light::Red);
+
+        preemption.update(false, &observation());
+        assert_eq!(preemption.stage, PreemptionStage::Recovering);
+        for _ in 0..240 + 90 {
+            preemption.update(false, &observation());
+        }
+        assert_eq!(preemption.stage, PreemptionStage::Idle);
+        let stages: Vec<PreemptionStage> = preemption.log.iter().map(|event| event.stage).collect();
+        assert_eq!(
+            stages,
+            [
+                PreemptionStage::Clearing,
+                PreemptionStage::Serving,
+                PreemptionStage::Recovering,
+                PreemptionStage::Idle
+            ]
+        );
+    }
+
+    #[test]
+    fn the_road_preempts_with_the_signals_it_runs() {
+        let preemption = Preemption::default();
+        assert_eq!(preemption.amber, AMBER_TICKS);
+        assert_eq!(preemption.all_red, ALL_RED_TICKS);
+        let signals = SignalConfig {
+            amber: 4.0,
+            all_red: 1.5,
+            ..SignalConfig::default()
+        };
+        let preemption = signals.road().unwrap().preemption;
+        assert_eq!(preemption.amber, 4 * TICKS_PER_SECOND);
+        assert_eq!(preemption.all_red, 90);
+    }
+}
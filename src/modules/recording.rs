@@ -0,0 +1,288 @@
+use std::io::Write;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::car::*;
+use super::geometry::*;
+use super::pedestrian::*;
+use super::road::*;
+use super::scenario::*;
+use super::se_base::*;
+use super::vehicle::*;
+
+/*
+One entry of a Recording:
+
+Car: a vehicle was spawned, by a key or by the generated demand, with everything needed to build it again, its speed being its desired speed in pixels per second
+Pedestrian: a pedestrian arrived at a crosswalk
+Key: a key was pressed in the window, kept so the log reads like the session did
+Lights: the vehicle lights changed color, and are now the given colors
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Car {
+        id: u64,
+        side: Side,
+        direction: Direction,
+        class: VehicleClass,
+        speed: f64,
+    },
+    Pedestrian {
+        id: u64,
+        leg: Side,
+        forward: bool,
+    },
+    Key {
+        key: String,
+    },
+    Lights {
+        north: Light,
+        east: Light,
+        south: Light,
+        west: Light,
+    },
+}
+
+/*
+A RecordedEvent with the tick it happened at, the value of Road::tick when the spawn or light change was made, before simulation_loop counts the tick.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub tick: u64,
+    #[serde(flatten)]
+    pub event: RecordedEvent,
+}
+
+/*
+The header line of a recording file: the seed of the run, the signal controller and the geometry of the intersection, which together with the recorded spawns are all a replay needs, and the tick the session ended at, which is where the replay stops. A recording without a geometry was made on the default one, and one without an end ends at its last record.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub seed: u64,
+    pub signals: SignalConfig,
+    #[serde(default)]
+    pub geometry: Geometry,
+    #[serde(default)]
+    pub end: Option<u64>,
+}
+
+/*
+A Recording logs a session while it runs: every spawn with its side, direction, class and speed, every pedestrian, key press and light change, tagged with the simulation tick. Road keeps one in Road::recording while recording, and the end of the header is set when the session ends.
+
+It is written as JSON Lines, the RecordingHeader on the first line and one Record per line after it.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub records: Vec<Record>,
+}
+
+impl Recording {
+    pub fn new(seed: u64, signals: SignalConfig, geometry: Geometry) -> Recording {
+        Recording {
+            header: RecordingHeader {
+                seed,
+                signals,
+                geometry,
+                end: None,
+            },
+            records: vec![],
+        }
+    }
+
+    pub fn push(&mut self, tick: u64, event: RecordedEvent) {
+        self.records.push(Record { tick, event });
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let error = |err: &dyn std::fmt::Display| format!("{}: {}", path.display(), err);
+        let mut out =
+            std::io::BufWriter::new(std::fs::File::create(path).map_err(|err| error(&err))?);
+        let header = serde_json::to_string(&self.header).map_err(|err| error(&err))?;
+        writeln!(out, "{}", header).map_err(|err| error(&err))?;
+        for record in &self.records {
+            let line = serde_json::to_string(record).map_err(|err| error(&err))?;
+            writeln!(out, "{}", line).map_err(|err| error(&err))?;
+        }
+        out.flush().map_err(|err| error(&err))
+    }
+
+    /*
+    Reads a recording written by Recording::save. Errors name the line they were found on.
+    */
+    pub fn load(path: &Path) -> Result<Recording, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.is_empty());
+        let header = match lines.next() {
+            Some((_, line)) => serde_json::from_str::<RecordingHeader>(line)
+                .map_err(|err| format!("{}:1: {}", path.display(), err))?,
+            None => return Err(format!("{}: the recording is empty", path.display())),
+        };
+        header
+            .signals
+            .validate()
+            .map_err(|err| format!("{}:1: signals.{}", path.display(), err))?;
+        header
+            .geometry
+            .validate()
+            .map_err(|err| format!("{}:1: geometry.{}", path.display(), err))?;
+        let mut records = vec![];
+        for (number, line) in lines {
+            let record: Record = serde_json::from_str(line)
+                .map_err(|err| format!("{}:{}: {}", path.display(), number + 1, err))?;
+            records.push(record);
+        }
+        Ok(Recording { header, records })
+    }
+
+    /*
+    Creates a Road that replays the recording from the start: it is laid out with the recorded geometry and run by the recorded controller with the recorded seed, and spawns the recorded vehicles and pedestrians instead of generating its own. Fails as SignalConfig::road does.
+    */
+    pub fn road(&self) -> Result<Road, String> {
+        let mut road = self.header.signals.road()?;
+        road.reseed(self.header.seed);
+        road.set_geometry(self.header.geometry);
+        road.playback = Some(Playback::new(self.records.clone(), self.end()));
+        Ok(road)
+    }
+
+    /*
+    Returns the tick the recorded session ended at: the end of the header, or else the tick after the last record.
+    */
+    pub fn end(&self) -> u64 {
+        self.header
+            .end
+            .unwrap_or_else(|| self.records.last().map_or(0, |record| record.tick + 1))
+    }
+}
+
+/*
+The Playback feeds a recording back into Road::simulation_loop: next is the first record not replayed yet, end is the tick the recorded session ended at, and diverged is the first tick at which the lights did not change the way they were recorded, which would mean the replay no longer matches the session.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playback {
+    pub records: Vec<Record>,
+    pub next: usize,
+    pub end: u64,
+    pub diverged: Option<u64>,
+}
+
+impl Playback {
+    pub fn new(records: Vec<Record>, end: u64) -> Playback {
+        Playback {
+            records,
+            next: 0,
+            end,
+            diverged: None,
+        }
+    }
+
+    /*
+    Returns the vehicles and pedestrians recorded at the given tick, skipping the key presses, and moves past them. Light changes are left for check_lights.
+    */
+    pub fn spawns(&mut self, tick: u64) -> (Vec<Car>, Vec<Pedestrian>) {
+        let mut cars = vec![];
+        let mut pedestrians = vec![];
+        while let Some(record) = self.records.get(self.next) {
+            if record.tick > tick {
+                break;
+            }
+            match &record.event {
+                RecordedEvent::Car {
+                    side,
+                    direction,
+                    class,
+                    speed,
+                    ..
+                } => cars.push(Car::with_speed(*side, *direction, *class, *speed)),
+                RecordedEvent::Pedestrian { leg, forward, .. } => {
+                    pedestrians.push(Pedestrian::new(*leg, *forward, record.tick))
+                }
+                RecordedEvent::Key { .. } => {}
+                RecordedEvent::Lights { .. } => break,
+            }
+            self.next += 1;
+        }
+        (cars, pedestrians)
+    }
+
+    /*
+    Compares the light change of the given tick, if any, with the recording, and moves past the recorded one. Past the end of the recorded session there is nothing to compare with.
+    */
+    pub fn check_lights(&mut self, tick: u64, change: Option<RecordedEvent>) {
+        if tick >= self.end {
+            return;
+        }
+        let recorded = match self.records.get(self.next) {
+            Some(record) if record.tick == tick => {
+                if let RecordedEvent::Lights { .. } = record.event {
+                    self.next += 1;
+                    Some(record.event.clone())
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        if recorded != change && self.diverged.is_none() {
+            self.diverged = Some(tick);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::constants::*;
+    use super::*;
+
+    #[test]
+    fn a_replay_reproduces_the_recorded_session() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios/am_peak.toml");
+        let scenario = Scenario::load(&path).unwrap();
+        let mut road = scenario.road().unwrap();
+        road.recording = Some(Recording::new(
+            road.seed,
+            scenario.signals.clone(),
+            road.geometry,
+        ));
+        let end = 300 * TICKS_PER_SECOND as u64 + 17;
+        while road.tick < end {
+            road.simulation_loop();
+        }
+        let mut recording = road.recording.take().unwrap();
+        recording.header.end = Some(road.tick);
+
+        let line = serde_json::to_string(&recording.header).unwrap();
+        let header: RecordingHeader = serde_json::from_str(&line).unwrap();
+        assert_eq!(header, recording.header);
+        assert_eq!(recording.end(), end);
+
+        let mut replay = recording.road().unwrap();
+        while replay.tick < recording.end() {
+            replay.simulation_loop();
+        }
+        assert_eq!(replay.playback.as_ref().unwrap().diverged, None);
+        assert_eq!(replay.stats(), road.stats());
+    }
+
+    #[test]
+    fn lights_are_not_checked_past_the_end() {
+        let lights = RecordedEvent::Lights {
+            north: Light::Green,
+            east: Light::Red,
+            south: Light::Red,
+            west: Light::Red,
+        };
+        let mut playback = Playback::new(vec![], 10);
+        playback.check_lights(10, Some(lights.clone()));
+        assert_eq!(playback.diverged, None);
+        playback.check_lights(9, Some(lights));
+        assert_eq!(playback.diverged, Some(9));
+    }
+}
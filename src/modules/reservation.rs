@@ -0,0 +1,272 @@
+use super::car::*;
+use super::conflict::*;
+use super::constants::*;
+use super::geometry::*;
+use super::se_base::*;
+
+/*
+A Reservation is the time-space slot granted to one car:
+
+car: the id of the car holding the reservation
+arrival: the tick at which the car is expected at its stop line and may enter
+windows: for every cell the car covers, the first and last tick during which the cell is held for it
+planned: the tick the plan starts from, the first tick the car drives after it was planned
+course: how far the car is planned to have travelled along its trajectory, course[0] at the start of tick planned and course[k] after k ticks of driving
+entered: whether the car has crossed its stop line on this reservation
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reservation {
+    pub car: u64,
+    pub arrival: u64,
+    pub windows: Vec<(Cell, u64, u64)>,
+    pub planned: u64,
+    pub course: Vec<f64>,
+    pub entered: bool,
+}
+
+impl Reservation {
+    /*
+    Works out the reservation a car would need if it drove from tick now on.
+    The car's path is played forward on a copy of the car: it drives on a free road until it reaches its stop line, which gives its arrival tick, and then through the intersection until its body has left every reserved cell.
+    Every cell returned by Cell::reserved for the given geometry, over the intersection box and the crosswalks, that the car's body covers along the way is held from the first to the last tick it covers it, widened by RESERVATION_MARGIN_TICKS on both sides.
+    */
+    pub fn plan(car: &Car, geometry: &Geometry, now: u64) -> Reservation {
+        let cells = Cell::reserved(geometry);
+        let mut ghost = car.clone();
+        let mut course = vec![ghost.travelled];
+        let mut windows: Vec<(Cell, u64, u64)> = vec![];
+        let mut arrival = None;
+        let mut tick = now.saturating_sub(1);
+        loop {
+            if arrival.is_none() && ghost.at_stop_line() {
+                arrival = Some(tick.max(now));
+            }
+            let mut covered = false;
+            for &cell in &cells {
+                if cell.overlaps(geometry, &ghost.body()) {
+                    covered = true;
+                    match windows.iter_mut().find(|(c, _, _)| *c == cell) {
+                        Some(window) => window.2 = tick,
+                        None => windows.push((cell, tick, tick)),
+                    }
+                }
+            }
+            if !covered && ghost.has_left_intersection() {
+                break;
+            }
+            ghost.follow(None, TrafficLight::new(Light::Green));
+            course.push(ghost.travelled);
+            tick += 1;
+        }
+        for window in windows.iter_mut() {
+            window.1 = window.1.saturating_sub(RESERVATION_MARGIN_TICKS);
+            window.2 += RESERVATION_MARGIN_TICKS;
+        }
+        Reservation {
+            car: car.id,
+            arrival: arrival.unwrap_or(now),
+            windows,
+            planned: now,
+            course,
+            entered: false,
+        }
+    }
+
+    pub fn overlaps(&self, other: &Reservation) -> bool {
+        self.windows.iter().any(|(cell, start, end)| {
+            other
+                .windows
+                .iter()
+                .any(|(c, s, e)| c == cell && start <= e && s <= end)
+        })
+    }
+
+    /*
+    Returns whether the car, about to drive tick now, has fallen behind its course by more than STOP_LINE_TOLERANCE, for example because it had to slow down for the car ahead of it. Past the end of its course it is behind as long as it is still held.
+    */
+    pub fn is_behind(&self, car: &Car, now: u64) -> bool {
+        match self.course.get(now.saturating_sub(self.planned) as usize) {
+            Some(travelled) => car.travelled + STOP_LINE_TOLERANCE < *travelled,
+            None => true,
+        }
+    }
+
+    pub fn end(&self) -> u64 {
+        self.windows
+            .iter()
+            .map(|(_, _, end)| *end)
+            .max()
+            .unwrap_or(self.arrival)
+    }
+}
+
+/*
+The IntersectionManager replaces the traffic lights with reservations, in the style of the Autonomous Intersection Management (AIM) protocol.
+
+Cars approaching their stop line ask for a reservation of the cells along their path. Requests are served first come, first served: a request is granted when none of its cells is already held by another car during the same ticks, and rejected otherwise. A rejected car slows down and asks again on the next tick.
+
+granted and rejected count the answers given, which is a measure of how contested the intersection is.
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IntersectionManager {
+    pub reservations: Vec<Reservation>,
+    pub granted: u64,
+    pub rejected: u64,
+}
+
+impl IntersectionManager {
+    pub fn new() -> IntersectionManager {
+        IntersectionManager::default()
+    }
+
+    /*
+    Handles a reservation request from a car driving through the intersection of the given geometry. Returns true when the car holds a reservation afterwards, either because it already had one or because this request was granted.
+    */
+    pub fn request(&mut self, car: &Car, geometry: &Geometry, now: u64) -> bool {
+        if self.reservation(car.id).is_some() {
+            return true;
+        }
+        let candidate = Reservation::plan(car, geometry, now);
+        if self
+            .reservations
+            .iter()
+            .any(|held| held.overlaps(&candidate))
+        {
+            self.rejected += 1;
+            return false;
+        }
+        self.granted += 1;
+        self.reservations.push(candidate);
+        true
+    }
+
+    pub fn reservation(&self, car: u64) -> Option<&Reservation> {
+        self.reservations.iter().find(|held| held.car == car)
+    }
+
+    /*
+    Returns whether the car may cross its stop line now: it holds a reservation and its arrival tick has come.
+    */
+    pub fn may_enter(&self, car: &Car, now: u64) -> bool {
+        match self.reservation(car.id) {
+            Some(held) => held.arrival <= now,
+            None => false,
+        }
+    }
+
+    /*
+    Marks the reservation of a car as entered, once the car has crossed its stop line on it.
+    */
+    pub fn enter(&mut self, car: u64) {
+        if let Some(held) = self.reservations.iter_mut().find(|held| held.car == car) {
+            held.entered = true;
+        }
+    }
+
+    /*
+    Cancels the reservation of a car, for example when it fell behind its plan before reaching its stop line.
+    */
+    pub fn cancel(&mut self, car: u64) {
+        self.reservations.retain(|held| held.car != car);
+    }
+
+    /*
+    Keeps the reservation of a car that has entered the intersection up to date, at the end of a tick.
+    The reservation is released once the car's body has left every cell of it, however late that is. A car that has fallen behind its course is planned again from where it is, and as it cannot stop any more its new plan stands: every reservation of a car that has not entered yet and overlaps it is cancelled, and that car stops at its line and asks again.
+    */
+    pub fn track(&mut self, car: &Car, geometry: &Geometry, now: u64) {
+        let Some(i) = self.reservations.iter().position(|held| held.car == car.id) else {
+            return;
+        };
+        if car.has_left_intersection()
+            && !self.reservations[i]
+                .windows
+                .iter()
+                .any(|(cell, _, _)| cell.overlaps(geometry, &car.body()))
+        {
+            self.reservations.remove(i);
+            return;
+        }
+        if !self.reservations[i].is_behind(car, now + 1) {
+            return;
+        }
+        let mut replanned = Reservation::plan(car, geometry, now + 1);
+        replanned.arrival = self.reservations[i].arrival;
+        replanned.entered = true;
+        self.reservations[i] = replanned;
+        let held = self.reservations[i].clone();
+        self.reservations
+            .retain(|other| other.car == held.car || other.entered || !other.overlaps(&held));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::super::scenario::*;
+    use super::super::trajectory::*;
+    use super::super::vehicle::*;
+    use super::*;
+
+    /*
+    Runs ten minutes of a scenario under reservations and checks on every tick that no two vehicle bodies overlap, wherever they are on the road.
+    */
+    fn assert_no_overlaps(file: &str) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("scenarios")
+            .join(file);
+        let mut scenario = Scenario::load(&path).unwrap();
+        scenario.signals.controller = ControllerKind::Reservations;
+        scenario.layout.pedestrians = 0.0;
+        let mut road = scenario.road().unwrap();
+        for _ in 0..600 * TICKS_PER_SECOND {
+            road.simulation_loop();
+            let mut cars: Vec<&Car> = road.cars_in_intersection.iter().collect();
+            for side in Side::ALL {
+                cars.extend(road.cars_before_stop(side));
+                cars.extend(road.cars_after_stop(side));
+            }
+            for (i, car) in cars.iter().enumerate() {
+                for other in &cars[i + 1..] {
+                    assert!(
+                        !polygons_overlap(&car.body(), &other.body()),
+                        "{}: tick {}, car {} overlaps car {}",
+                        file,
+                        road.tick,
+                        car.id,
+                        other.id
+                    );
+                }
+            }
+        }
+        let manager = road.manager.as_ref().unwrap();
+        assert!(manager.granted > 0 && manager.rejected > 0);
+    }
+
+    #[test]
+    fn reserved_cars_never_overlap() {
+        assert_no_overlaps("am_peak.toml");
+        assert_no_overlaps("two_lane.toml");
+    }
+
+    #[test]
+    fn a_car_slowed_down_falls_behind_its_plan() {
+        let geometry = Geometry::default();
+        let mut car = Car::with_speed(
+            Side::FromNorth,
+            Direction::Straight,
+            VehicleClass::PassengerCar,
+            150.0,
+        );
+        car.route(&geometry);
+        let plan = Reservation::plan(&car, &geometry, 100);
+        assert!(!plan.is_behind(&car, 100));
+        let mut free = car.clone();
+        free.follow(None, TrafficLight::new(Light::Green));
+        assert!(!plan.is_behind(&free, 101));
+        assert!(plan.is_behind(&car, 101));
+        assert!(plan.arrival > 100);
+        assert!(plan.windows.iter().all(|(_, start, end)| start <= end));
+    }
+}
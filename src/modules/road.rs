@@ -1,16 +1,47 @@
+extern crate rand;
+
 use super::car::*;
+use super::conflict::*;
 use super::constants::*;
+use super::controller::*;
+use super::demand::*;
+use super::geometry::*;
+use super::pedestrian::*;
+use super::preemption::*;
+use super::recording::*;
+use super::reservation::*;
 use super::se_base::*;
+use super::speeds::*;
+use super::stats::*;
+use super::trajectory::*;
+use super::turning::*;
+use super::vehicle::*;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 /*
 The struct represents a Road object with various fields to track the state of cars and traffic lights at an intersection.
+geometry is the layout of the intersection every car is routed through, and conflicts the conflict matrix of its movements.
+The controller decides the light state on every tick, preemption takes over while an emergency vehicle is served, and an IntersectionManager is set, in which case reservations replace the lights as the gate into the intersection.
+Pedestrians wait at and walk over the four crosswalks under the pedestrian_lights derived from the vehicle lights, pedestrian_demand spawns them at random (in pedestrians per hour on each crosswalk, 0 to spawn none) and crossed keeps the ones that reached the far curb.
+demand generates vehicle arrivals on the approaches, on top of the ones spawned by hand, while mix, turning and speeds set the class, the movement and the desired speed of the vehicles made by Road::generate_car.
+collector records every vehicle's trip and the per-approach time series, summed up by Road::stats.
+entering holds, in their order of arrival, the spawned cars that found no room yet at the start of their lane, or whose turn would sweep over a vehicle of another side at its stop line; they join the cars_before_stop_* queues as their lanes clear.
+While recording is set every spawn, key press and light change is logged into it, and while playback is set the recorded vehicles and pedestrians are spawned instead of generated ones.
+tick counts the ticks simulated so far, each one SIMULATION_STEP seconds of simulated time, and next_id is the id given to the last spawned car or pedestrian.
+All randomness of the simulation comes from rng, a ChaCha8 generator started from seed, so the same seed and the same spawns give the same run.
+A Road can be cloned, controller and generator included, to play a run forward from the same state; it cannot be compared, as the controller is a trait object.
 */
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub struct Road {
+    pub geometry: Geometry,
+    pub conflicts: ConflictMatrix,
     pub cars_before_stop_north: Vec<Car>,
     pub cars_before_stop_south: Vec<Car>,
     pub cars_before_stop_east: Vec<Car>,
     pub cars_before_stop_west: Vec<Car>,
+    pub entering: Vec<Car>,
     pub cars_in_intersection: Vec<Car>,
     pub cars_after_stop_north: Vec<Car>,
     pub cars_after_stop_south: Vec<Car>,
@@ -20,6 +51,30 @@ pub struct Road {
     pub east_lights: TrafficLight,
     pub south_lights: TrafficLight,
     pub west_lights: TrafficLight,
+    pub controller: Box<dyn SignalController>,
+    pub preemption: Preemption,
+    pub manager: Option<IntersectionManager>,
+    pub pedestrians: Vec<Pedestrian>,
+    pub crossed: Vec<Pedestrian>,
+    pub pedestrian_lights: [PedestrianLight; 4],
+    pub pedestrian_demand: f64,
+    pub demand: Demand,
+    pub mix: TrafficMix,
+    pub turning: TurningProportions,
+    pub speeds: SpeedDistribution,
+    pub collector: StatsCollector,
+    pub recording: Option<Recording>,
+    pub playback: Option<Playback>,
+    pub tick: u64,
+    pub next_id: u64,
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+}
+
+impl Default for Road {
+    fn default() -> Road {
+        Road::new()
+    }
 }
 
 impl Road {
@@ -28,326 +83,949 @@ impl Road {
 
     Eight fields representing lists of cars at different positions on the road are initialized as empty vectors.
     Four fields representing traffic lights at different directions are initialized with a TrafficLight struct having a color field set to Light::Red, indicating that all traffic lights are initially red.
+    The lights are driven by the default LongestQueue controller.
+    The random number generator gets a random seed; use Road::reseed to pick it.
     */
     pub fn new() -> Road {
+        Road::with_controller(LongestQueue::default())
+    }
+
+    /*
+    Creates a Road the same way as Road::new, but with the given SignalController deciding the light state.
+    */
+    pub fn with_controller<C: SignalController + 'static>(controller: C) -> Road {
+        let seed = rand::random();
         Road {
+            geometry: Geometry::default(),
+            conflicts: ConflictMatrix::default(),
             cars_before_stop_north: vec![],
             cars_before_stop_south: vec![],
             cars_before_stop_east: vec![],
             cars_before_stop_west: vec![],
+            entering: vec![],
             cars_in_intersection: vec![],
             cars_after_stop_north: vec![],
             cars_after_stop_south: vec![],
             cars_after_stop_east: vec![],
             cars_after_stop_west: vec![],
-            north_lights: TrafficLight { color: Light::Red },
-            east_lights: TrafficLight { color: Light::Red },
-            south_lights: TrafficLight { color: Light::Red },
-            west_lights: TrafficLight { color: Light::Red },
+            north_lights: TrafficLight::new(Light::Red),
+            east_lights: TrafficLight::new(Light::Red),
+            south_lights: TrafficLight::new(Light::Red),
+            west_lights: TrafficLight::new(Light::Red),
+            controller: Box::new(controller),
+            preemption: Preemption::default(),
+            manager: None,
+            pedestrians: vec![],
+            crossed: vec![],
+            pedestrian_lights: [PedestrianLight::DontWalk; 4],
+            pedestrian_demand: 0.0,
+            demand: Demand::default(),
+            mix: TrafficMix::default(),
+            turning: TurningProportions::default(),
+            speeds: SpeedDistribution::default(),
+            collector: StatsCollector::default(),
+            recording: None,
+            playback: None,
+            tick: 0,
+            next_id: 0,
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /*
+    Restarts the random number generator from the given seed and records it.
+    */
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    /*
+    Lays the intersection out with the given geometry and works out the conflicts of its movements. Cars spawned from then on are routed through it, so it is set before the first spawn.
+    */
+    pub fn set_geometry(&mut self, geometry: Geometry) {
+        self.conflicts = ConflictMatrix::new(&geometry);
+        self.geometry = geometry;
+    }
+
+    /*
+    Creates a Road without signal control: cars enter the intersection through reservations granted by the given IntersectionManager. The lights stay red and are not consulted. There is no pedestrian phase either, the crosswalks stay at DontWalk, so such a Road is not meant to have pedestrian_demand: Scenario::validate and the binary reject the combination.
+    */
+    pub fn with_manager(manager: IntersectionManager) -> Road {
+        let mut road = Road::new();
+        road.manager = Some(manager);
+        road
+    }
+
+    /*
+    Builds the Observation handed to the controller: the length of every cars_before_stop_* queue, the number of cars in the intersection, the elapsed ticks, the current lights and the number of pedestrians on each crosswalk.
+    */
+    pub fn observe(&self) -> Observation {
+        let mut queues = [0; 4];
+        queues[Side::FromNorth.index()] = self.cars_before_stop_north.len();
+        queues[Side::FromEast.index()] = self.cars_before_stop_east.len();
+        queues[Side::FromSouth.index()] = self.cars_before_stop_south.len();
+        queues[Side::FromWest.index()] = self.cars_before_stop_west.len();
+        let mut pedestrians = [0; 4];
+        for pedestrian in &self.pedestrians {
+            pedestrians[pedestrian.leg.index()] += 1;
+        }
+        Observation {
+            queues,
+            occupancy: self.cars_in_intersection.len(),
+            elapsed: self.tick,
+            lights: SignalState {
+                north: self.north_lights.clone(),
+                east: self.east_lights.clone(),
+                south: self.south_lights.clone(),
+                west: self.west_lights.clone(),
+            },
+            pedestrians,
+        }
+    }
+
+    /*
+    Returns the cars_before_stop_* queue of the given side.
+    */
+    pub fn cars_before_stop(&self, side: Side) -> &Vec<Car> {
+        match side {
+            Side::FromNorth => &self.cars_before_stop_north,
+            Side::FromEast => &self.cars_before_stop_east,
+            Side::FromSouth => &self.cars_before_stop_south,
+            Side::FromWest => &self.cars_before_stop_west,
+        }
+    }
+
+    pub fn cars_before_stop_mut(&mut self, side: Side) -> &mut Vec<Car> {
+        match side {
+            Side::FromNorth => &mut self.cars_before_stop_north,
+            Side::FromEast => &mut self.cars_before_stop_east,
+            Side::FromSouth => &mut self.cars_before_stop_south,
+            Side::FromWest => &mut self.cars_before_stop_west,
+        }
+    }
+
+    pub fn lights(&self, side: Side) -> &TrafficLight {
+        match side {
+            Side::FromNorth => &self.north_lights,
+            Side::FromEast => &self.east_lights,
+            Side::FromSouth => &self.south_lights,
+            Side::FromWest => &self.west_lights,
+        }
+    }
+
+    /*
+    Creates a car coming from the given side, with its class drawn from the traffic mix and its direction from the turning proportions. Generated demand and the keys of the binary spawn their cars through it.
+    */
+    pub fn generate_car(&mut self, side: Side) -> Car {
+        let class = self.mix.sample(&mut self.rng);
+        let direction = self.turning.sample(side, &mut self.rng);
+        let speed = self.speeds.sample(&mut self.rng);
+        Car::with_speed(side, direction, class, speed)
+    }
+
+    /*
+    Assigns the next id to the car, routes it through the road's geometry, opens its trip record and puts it at the back of the cars_before_stop_* queue of its side, or in entering while there is no room for it at the start of its lane.
+    */
+    pub fn spawn(&mut self, mut car: Car) {
+        self.next_id += 1;
+        car.id = self.next_id;
+        car.route(&self.geometry);
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(
+                self.tick,
+                RecordedEvent::Car {
+                    id: car.id,
+                    side: car.side,
+                    direction: car.direction,
+                    class: car.class,
+                    speed: car.idm.desired_speed,
+                },
+            );
+        }
+        self.collector.spawned(&car, self.tick);
+        self.entering.push(car);
+        self.admit();
+    }
+
+    /*
+    Moves the cars of entering onto the road, in their order of arrival, as far as there is room for them. A car has room once the tail of every car in its lane is its minimum gap past the start of the lane, whatever order pulling aside left the queue in, and it never overtakes an earlier arrival held in the same lane.
+    A long vehicle that would turn over the approach of another side also waits until sweep_clear_for lets it through, so that it never finds a vehicle standing in its way at the stop line: the vehicles arriving after it hold back for it.
+    */
+    fn admit(&mut self) {
+        let mut held: Vec<Car> = vec![];
+        for car in std::mem::take(&mut self.entering) {
+            let lane = car.trajectory.lane;
+            let blocked = held
+                .iter()
+                .any(|other| other.side == car.side && other.trajectory.lane == lane)
+                || self
+                    .cars_before_stop(car.side)
+                    .iter()
+                    .filter(|ahead| ahead.trajectory.lane == lane)
+                    .any(|ahead| ahead.travelled - (ahead.length as f64) < car.idm.min_gap)
+                || !self.sweep_clear_for(&car);
+            if blocked {
+                held.push(car);
+            } else {
+                self.cars_before_stop_mut(car.side).push(car);
+            }
+        }
+        self.entering = held;
+    }
+
+    /*
+    Returns the cars_after_stop_* queue of the given leg.
+    */
+    pub fn cars_after_stop(&self, leg: Side) -> &Vec<Car> {
+        match leg {
+            Side::FromNorth => &self.cars_after_stop_north,
+            Side::FromEast => &self.cars_after_stop_east,
+            Side::FromSouth => &self.cars_after_stop_south,
+            Side::FromWest => &self.cars_after_stop_west,
+        }
+    }
+
+    pub fn cars_after_stop_mut(&mut self, leg: Side) -> &mut Vec<Car> {
+        match leg {
+            Side::FromNorth => &mut self.cars_after_stop_north,
+            Side::FromEast => &mut self.cars_after_stop_east,
+            Side::FromSouth => &mut self.cars_after_stop_south,
+            Side::FromWest => &mut self.cars_after_stop_west,
+        }
+    }
+
+    pub fn pedestrian_light(&self, leg: Side) -> PedestrianLight {
+        self.pedestrian_lights[leg.index()]
+    }
+
+    /*
+    Assigns the next id to the pedestrian and puts it at the curb of its crosswalk.
+    */
+    pub fn spawn_pedestrian(&mut self, mut pedestrian: Pedestrian) {
+        self.next_id += 1;
+        pedestrian.id = self.next_id;
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(
+                self.tick,
+                RecordedEvent::Pedestrian {
+                    id: pedestrian.id,
+                    leg: pedestrian.leg,
+                    forward: pedestrian.forward,
+                },
+            );
         }
+        self.pedestrians.push(pedestrian);
     }
 
     /*
-    Simulation loop for a traffic intersection that simulates the movement of cars through an intersection with traffic lights.
+    Logs a key press into the recording, if one is running.
+    */
+    pub fn record_key(&mut self, key: &str) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.push(
+                self.tick,
+                RecordedEvent::Key {
+                    key: key.to_string(),
+                },
+            );
+        }
+    }
+
+    /*
+    Checks whether cars must keep off the crosswalk of the given leg: a pedestrian is walking over it, or is waiting at its curb while it shows Walk.
+    */
+    pub fn crosswalk_busy(&self, leg: Side) -> bool {
+        let walk = self.pedestrian_light(leg) == PedestrianLight::Walk;
+        self.pedestrians
+            .iter()
+            .any(|pedestrian| pedestrian.leg == leg && (pedestrian.crossing || walk))
+    }
 
-    It checks the state of the traffic lights and updates them based on certain conditions.
-    It moves cars through the intersection based on their direction and the state of the traffic lights.
+    /*
+    Checks whether a pedestrian may step onto the crosswalk of the given leg: no car in the intersection is entering or leaving by that leg, and no car that left by it is still over the crosswalk.
+    */
+    pub fn crosswalk_clear(&self, leg: Side) -> bool {
+        let (x, y, width, height) = self.geometry.crosswalk(leg);
+        self.cars_in_intersection
+            .iter()
+            .all(|car| car.side != leg && car.movement().exit_leg() != leg)
+            && self.cars_after_stop(leg).iter().all(|car| {
+                let crosswalk = rectangle(x as f64, y as f64, width as f64, height as f64);
+                !polygons_overlap(&car.body(), &crosswalk)
+            })
+    }
+
+    /*
+    Returns the aggregates of the run so far: vehicle delay, stops and travel time, pedestrian waiting time and the figures of every movement.
+    */
+    pub fn stats(&self) -> Stats {
+        self.collector.stats(
+            self.pedestrians
+                .iter()
+                .chain(self.crossed.iter())
+                .map(|pedestrian| pedestrian.wait(self.tick) as f64),
+        )
+    }
+
+    /*
+    Checks whether the car may enter the intersection alongside the cars already in it.
+    Cars from other sides must not make a movement that conflicts with the car's movement. A car from the same side and lane must have travelled far enough past the point the car stops at, given its length, to leave room for the car to follow it in, even after a turn.
+    The car also yields to pedestrians on the crosswalks it drives over, the one of its own leg and the one of the leg it exits by, and waits for the vehicles of other sides it would sweep over, as sweep_clear_for finds them.
+    */
+    pub fn intersection_clear_for(&self, car: &Car) -> bool {
+        let movement = car.movement();
+        if self.crosswalk_busy(car.side) || self.crosswalk_busy(movement.exit_leg()) {
+            return false;
+        }
+        self.sweep_clear_for(car)
+            && self.cars_in_intersection.iter().all(|other| {
+                if other.side != car.side {
+                    !self.conflicts.between(&movement, &other.movement())
+                } else if other.trajectory.lane == car.trajectory.lane {
+                    other.travelled - car.stop_point() > (SECURITY_DISTANCE + other.length) as f64
+                } else {
+                    true
+                }
+            })
+    }
+
+    /*
+    Checks whether the car may drive through without sweeping over a vehicle of another side on its approach: every such vehicle is, or can still stop, as far short of its stop line as its ConflictMatrix::hold for the car. Only long vehicles turning reach the approaches of other sides.
+    */
+    pub fn sweep_clear_for(&self, car: &Car) -> bool {
+        let movement = car.movement();
+        Side::ALL
+            .into_iter()
+            .filter(|&side| side != car.side)
+            .flat_map(|side| self.cars_before_stop(side))
+            .all(|other| {
+                let hold = self.conflicts.hold(car.class, &movement, &other.movement());
+                hold == 0.0
+                    || other.travelled + other.idm.stopping_distance(other.speed)
+                        <= other.trajectory.stop_line - hold + STOP_LINE_TOLERANCE
+            })
+    }
+
+    /*
+    Looks for an emergency vehicle within PREEMPTION_DETECTION_DISTANCE of its stop line and returns its side and id.
+    */
+    pub fn detect_emergency(&self) -> Option<(Side, u64)> {
+        for side in Side::ALL {
+            if let Some(car) = self.cars_before_stop(side).iter().find(|car| {
+                car.is_emergency()
+                    && car.distance_to_stop_line() <= PREEMPTION_DETECTION_DISTANCE as f64
+            }) {
+                return Some((side, car.id));
+            }
+        }
+        None
+    }
+
+    /*
+    Returns the colors of the north, east, south and west lights.
+    */
+    pub fn light_colors(&self) -> [Light; 4] {
+        [
+            self.north_lights.color,
+            self.east_lights.color,
+            self.south_lights.color,
+            self.west_lights.color,
+        ]
+    }
+
+    /*
+    Returns the simulated time in seconds, tick steps of SIMULATION_STEP.
+    */
+    pub fn time(&self) -> f64 {
+        self.tick as f64 * SIMULATION_STEP
+    }
+
+    /*
+    Simulation loop for a traffic intersection that simulates the movement of cars through an intersection with traffic lights. One call advances the simulation by one fixed step of SIMULATION_STEP seconds, whatever the rate it is called at, which is left to a Clock.
+
+    It lets the cars held in entering onto the road where their lanes have cleared, then spawns the vehicles and pedestrians generated by the demand for this tick, or the recorded ones during a playback.
+    It asks the signal controller for the new state of the traffic lights and applies it, unless an emergency vehicle preemption overrides it, and derives the pedestrian lights from it.
+    It moves cars through the intersection based on their direction and the state of the traffic lights. A car at its stop line enters on green, or on amber when it decides to go, as long as no conflicting movement is inside the intersection and no pedestrian is on its crosswalks; otherwise it is marked as waiting.
     It checks for collisions and prevents cars from moving if there is a car in front of them.
     It updates the position of cars in the intersection and moves them to the next stage of their journey (e.g., from "before stop" to "in intersection" to "after stop").
+    Then it moves the pedestrians, who step off the curb on Walk once no car is on their crosswalk.
+    Last it hands the new state to the StatsCollector.
+    A light change is logged into the recording, and checked against it during a playback.
     */
     pub fn simulation_loop(&mut self) {
-        if self.south_lights.color == Light::Green
-            || self.north_lights.color == Light::Green
-            || self.east_lights.color == Light::Green
-            || self.west_lights.color == Light::Green
-        {
-            self.east_lights = TrafficLight { color: Light::Red };
-            self.north_lights = TrafficLight { color: Light::Red };
-            self.south_lights = TrafficLight { color: Light::Red };
-            self.west_lights = TrafficLight { color: Light::Red };
+        self.admit();
+        if let Some(playback) = self.playback.as_mut() {
+            let (cars, pedestrians) = playback.spawns(self.tick);
+            for car in cars {
+                self.spawn(car);
+            }
+            for pedestrian in pedestrians {
+                self.spawn_pedestrian(pedestrian);
+            }
         } else {
-            let a = self.cars_before_stop_east.len();
-            let b = self.cars_before_stop_west.len();
-            let c = self.cars_before_stop_north.len();
-            let d = self.cars_before_stop_south.len();
-            if a >= b && a >= c && a >= d {
-                self.east_lights = TrafficLight {
-                    color: Light::Green,
-                };
-                self.north_lights = TrafficLight { color: Light::Red };
-                self.south_lights = TrafficLight { color: Light::Red };
-                self.west_lights = TrafficLight { color: Light::Red };
-            } else {
-                if b >= a && b >= c && b >= d {
-                    self.east_lights = TrafficLight { color: Light::Red };
-                    self.north_lights = TrafficLight { color: Light::Red };
-                    self.south_lights = TrafficLight { color: Light::Red };
-                    self.west_lights = TrafficLight {
-                        color: Light::Green,
-                    };
-                } else {
-                    if c >= a && c >= b && c >= d {
-                        self.east_lights = TrafficLight { color: Light::Red };
-                        self.north_lights = TrafficLight {
-                            color: Light::Green,
-                        };
-                        self.south_lights = TrafficLight { color: Light::Red };
-                        self.west_lights = TrafficLight { color: Light::Red };
-                    } else {
-                        self.east_lights = TrafficLight { color: Light::Red };
-                        self.north_lights = TrafficLight { color: Light::Red };
-                        self.south_lights = TrafficLight {
-                            color: Light::Green,
-                        };
-                        self.west_lights = TrafficLight { color: Light::Red };
-                    }
+            let mut demand = std::mem::take(&mut self.demand);
+            for side in demand.arrivals(self.tick, &mut self.rng) {
+                let car = self.generate_car(side);
+                self.spawn(car);
+            }
+            self.demand = demand;
+            let chance =
+                (self.pedestrian_demand / (3600 * TICKS_PER_SECOND) as f64).clamp(0.0, 1.0);
+            for leg in Side::ALL {
+                if chance > 0.0 && self.rng.random_bool(chance) {
+                    let forward = self.rng.random_bool(0.5);
+                    let pedestrian = Pedestrian::new(leg, forward, self.tick);
+                    self.spawn_pedestrian(pedestrian);
                 }
             }
         }
 
-        /*
-        If there are cars in the intersection, move the first one and check if it has exited the intersection.
-        If it has, move it to the next stage of its journey (e.g., from "in intersection" to "after stop").
-        If it hasn't, move the next car in the intersection if there is enough space.
-        */
-        if self.cars_in_intersection.len() != 0 {
-            self.cars_in_intersection[0].moove(TrafficLight {
-                color: Light::Green,
-            });
-            if (self.cars_in_intersection[0].x > OUTPUT_WIDTH / 2 + CAR_WIDTH
-                || self.cars_in_intersection[0].x < OUTPUT_WIDTH / 2 - 2 * CAR_WIDTH)
-                || (self.cars_in_intersection[0].y > OUTPUT_HEIGHT / 2 + CAR_HEIGHT
-                    || self.cars_in_intersection[0].y < OUTPUT_HEIGHT / 2 - 2 * CAR_HEIGHT)
-            {
-                if (self.cars_in_intersection[0].side == Side::FromEast
-                    && self.cars_in_intersection[0].direction == Direction::Left)
-                    || (self.cars_in_intersection[0].side == Side::FromSouth
-                        && self.cars_in_intersection[0].direction == Direction::Straight)
-                    || (self.cars_in_intersection[0].side == Side::FromWest
-                        && self.cars_in_intersection[0].direction == Direction::Right)
-                {
-                    self.cars_after_stop_north
-                        .push(self.cars_in_intersection[0].clone());
-                    self.cars_in_intersection.pop();
-                } else if (self.cars_in_intersection[0].side == Side::FromSouth
-                    && self.cars_in_intersection[0].direction == Direction::Left)
-                    || (self.cars_in_intersection[0].side == Side::FromWest
-                        && self.cars_in_intersection[0].direction == Direction::Straight)
-                    || (self.cars_in_intersection[0].side == Side::FromNorth
-                        && self.cars_in_intersection[0].direction == Direction::Right)
-                {
-                    self.cars_after_stop_east
-                        .push(self.cars_in_intersection[0].clone());
-                    self.cars_in_intersection.pop();
-                } else if (self.cars_in_intersection[0].side == Side::FromWest
-                    && self.cars_in_intersection[0].direction == Direction::Left)
-                    || (self.cars_in_intersection[0].side == Side::FromNorth
-                        && self.cars_in_intersection[0].direction == Direction::Straight)
-                    || (self.cars_in_intersection[0].side == Side::FromEast
-                        && self.cars_in_intersection[0].direction == Direction::Right)
-                {
-                    self.cars_after_stop_south
-                        .push(self.cars_in_intersection[0].clone());
-                    self.cars_in_intersection.pop();
-                } else if (self.cars_in_intersection[0].side == Side::FromNorth
-                    && self.cars_in_intersection[0].direction == Direction::Left)
-                    || (self.cars_in_intersection[0].side == Side::FromEast
-                        && self.cars_in_intersection[0].direction == Direction::Straight)
-                    || (self.cars_in_intersection[0].side == Side::FromSouth
-                        && self.cars_in_intersection[0].direction == Direction::Right)
-                {
-                    self.cars_after_stop_west
-                        .push(self.cars_in_intersection[0].clone());
-                    self.cars_in_intersection.pop();
-                }
+        let colors = self.light_colors();
+        if self.manager.is_none() {
+            let observation = self.observe();
+            let mut state = self.controller.update(&observation);
+            if self.preemption.is_active() {
+                let car = self.preemption.car;
+                let waiting = self
+                    .cars_before_stop(self.preemption.side)
+                    .iter()
+                    .any(|queued| queued.id == car);
+                self.preemption.update(waiting, &observation);
+            } else if let Some((side, car)) = self.detect_emergency() {
+                self.preemption
+                    .start(side, car, &observation.lights, &observation);
+            }
+            if self.preemption.is_active() {
+                state = self.preemption.state();
+            }
+            let clearance = self.geometry.clearance_ticks();
+            for leg in Side::ALL {
+                self.pedestrian_lights[leg.index()] =
+                    PedestrianLight::for_leg(leg, &state, clearance);
             }
+            self.north_lights = state.north;
+            self.east_lights = state.east;
+            self.south_lights = state.south;
+            self.west_lights = state.west;
         }
+        let change = (self.light_colors() != colors).then(|| {
+            let [north, east, south, west] = self.light_colors();
+            RecordedEvent::Lights {
+                north,
+                east,
+                south,
+                west,
+            }
+        });
+        if let Some(playback) = self.playback.as_mut() {
+            playback.check_lights(self.tick, change.clone());
+        }
+        if let Some(recording) = self.recording.as_mut()
+            && let Some(change) = change
+        {
+            recording.push(self.tick, change);
+        }
+        self.tick += 1;
 
         /*
-        Move cars that are leaving the intersection after the green light.
-        For each car, check if the car in front of it has moved far enough away (i.e., more than the SECURITY_DISTANCE). If so, move the car and update the front car's position.
+        Move every car in the intersection. Each car follows the car ahead of it on the same path, the last car of the same movement that entered before it, or else the last car in its lane on the leg it exits by, and drives on a free road when there is none. Each car that has left the intersection box goes on to the next stage of its journey, the cars_after_stop_* queue of the leg it exits by.
         */
-        if self.cars_after_stop_east.len() > 0 {
-            self.cars_after_stop_east[0].moove(TrafficLight {
-                color: Light::Green,
-            });
-            let mut front_car = self.cars_after_stop_east[0].clone();
-            for i in 1..self.cars_after_stop_east.len() {
-                if self.cars_after_stop_east[i].x - self.cars_after_stop_east[i].velocity
-                    > front_car.x + SECURITY_DISTANCE
-                {
-                    self.cars_after_stop_east[i].moove(TrafficLight {
-                        color: Light::Green,
-                    });
-                    front_car = self.cars_after_stop_east[i].clone();
-                }
+        for i in 0..self.cars_in_intersection.len() {
+            let car = &self.cars_in_intersection[i];
+            let movement = car.movement();
+            let leader = self.cars_in_intersection[..i]
+                .iter()
+                .rev()
+                .find(|ahead| ahead.movement() == movement)
+                .or_else(|| {
+                    self.cars_after_stop(movement.exit_leg())
+                        .iter()
+                        .rev()
+                        .find(|ahead| ahead.trajectory.lane == car.trajectory.lane)
+                })
+                .map(|ahead| (car.gap_to(ahead), ahead.speed));
+            self.cars_in_intersection[i].follow(leader, TrafficLight::new(Light::Green));
+        }
+        let mut i = 0;
+        while i < self.cars_in_intersection.len() {
+            if self.cars_in_intersection[i].has_left_intersection() {
+                let car = self.cars_in_intersection.remove(i);
+                self.cars_after_stop_mut(car.movement().exit_leg())
+                    .push(car);
+            } else {
+                i += 1;
             }
         }
 
         /*
         Move cars that are leaving the intersection after the green light.
-        For each car, check if the car in front of it has moved far enough away (i.e., more than the SECURITY_DISTANCE). If so, move the car and update the front car's position.
-        This simulates the movement of cars in the intersection and ensures that cars do not collide.
+        The first car of each lane drives on a free road and every other car follows the car in front of it in its lane, which keeps them from colliding.
+        A car whose tail has passed the end of the road is dropped. Its trip was closed by the StatsCollector on the tick it joined the queue, long before.
         */
-        if self.cars_after_stop_north.len() > 0 {
-            self.cars_after_stop_north[0].moove(TrafficLight {
-                color: Light::Green,
-            });
-            let mut front_car = self.cars_after_stop_north[0].clone();
-            for i in 1..self.cars_after_stop_north.len() {
-                if self.cars_after_stop_north[i].y - self.cars_after_stop_north[i].velocity
-                    > front_car.y + SECURITY_DISTANCE
-                {
-                    self.cars_after_stop_north[i].moove(TrafficLight {
-                        color: Light::Green,
-                    });
-                    front_car = self.cars_after_stop_north[i].clone();
-                }
+        for leg in Side::ALL {
+            let queue = self.cars_after_stop_mut(leg);
+            for i in 0..queue.len() {
+                let leader = lane_ahead(queue, i)
+                    .map(|ahead| (queue[i].gap_to(&queue[ahead]), queue[ahead].speed));
+                queue[i].follow(leader, TrafficLight::new(Light::Green));
             }
+            queue.retain(|car| !car.has_left_road());
         }
 
         /*
-        Move cars that are leaving the intersection after the green light.
-        For each car, check if the car in front of it has moved far enough away (i.e., more than the SECURITY_DISTANCE). If so, move the car and update the front car's position.
-        This simulates the movement of cars in the intersection and ensures that cars do not collide.
+        Move the cars waiting to enter the intersection, one approach at a time.
         */
-        if self.cars_after_stop_west.len() > 0 {
-            self.cars_after_stop_west[0].moove(TrafficLight {
-                color: Light::Green,
-            });
-            let mut front_car = self.cars_after_stop_west[0].clone();
-            for i in 1..self.cars_after_stop_west.len() {
-                if self.cars_after_stop_west[i].x + self.cars_after_stop_west[i].velocity
-                    < front_car.x - SECURITY_DISTANCE
-                {
-                    self.cars_after_stop_west[i].moove(TrafficLight {
-                        color: Light::Green,
-                    });
-                    front_car = self.cars_after_stop_west[i].clone();
-                }
-            }
+        for side in [
+            Side::FromNorth,
+            Side::FromSouth,
+            Side::FromEast,
+            Side::FromWest,
+        ] {
+            let mut queue = std::mem::take(self.cars_before_stop_mut(side));
+            self.advance_queue(side, &mut queue);
+            *self.cars_before_stop_mut(side) = queue;
         }
 
         /*
-        Move cars that are leaving the intersection after the green light.
-        For each car, check if the car in front of it has moved far enough away (i.e., more than the SECURITY_DISTANCE). If so, move the car and update the front car's position.
-        This simulates the movement of cars in the intersection and ensures that cars do not collide.
+        Move the pedestrians. A pedestrian who reached the far curb goes to crossed.
         */
-        if self.cars_after_stop_south.len() > 0 {
-            self.cars_after_stop_south[0].moove(TrafficLight {
-                color: Light::Green,
-            });
-            let mut front_car = self.cars_after_stop_south[0].clone();
-            for i in 1..self.cars_after_stop_south.len() {
-                if self.cars_after_stop_south[i].y + self.cars_after_stop_south[i].velocity
-                    < front_car.y - SECURITY_DISTANCE
-                {
-                    self.cars_after_stop_south[i].moove(TrafficLight {
-                        color: Light::Green,
-                    });
-                    front_car = self.cars_after_stop_south[i].clone();
-                }
+        let mut pedestrians = std::mem::take(&mut self.pedestrians);
+        for pedestrian in pedestrians.iter_mut() {
+            let may_start = self.crosswalk_clear(pedestrian.leg);
+            pedestrian.walk(self.pedestrian_light(pedestrian.leg), may_start, self.tick);
+        }
+        let (crossed, walking): (Vec<Pedestrian>, Vec<Pedestrian>) = pedestrians
+            .into_iter()
+            .partition(|pedestrian| pedestrian.has_crossed(&self.geometry));
+        self.pedestrians = walking;
+        self.crossed.extend(crossed);
+
+        if let Some(mut manager) = self.manager.take() {
+            for car in self.cars_in_intersection.iter().chain(
+                Side::ALL
+                    .iter()
+                    .flat_map(|&leg| self.cars_after_stop(leg).iter()),
+            ) {
+                manager.track(car, &self.geometry, self.tick);
             }
+            self.manager = Some(manager);
         }
 
-        if self.cars_before_stop_north.len() > 0 {
-            if self.cars_before_stop_north[0].y < (OUTPUT_HEIGHT / 2 - 2 * CAR_HEIGHT) {
-                self.cars_before_stop_north[0].moove(self.north_lights.clone());
+        let mut collector = std::mem::take(&mut self.collector);
+        collector.record(self);
+        self.collector = collector;
+    }
 
-                let mut front_car_y = self.cars_before_stop_north[0].y;
-                for i in 1..self.cars_before_stop_north.len() {
-                    if self.cars_before_stop_north[i].y + self.cars_before_stop_north[i].velocity
-                        < front_car_y - SECURITY_DISTANCE
-                    {
-                        self.cars_before_stop_north[i].moove(self.north_lights.clone());
-                        front_car_y = self.cars_before_stop_north[i].y;
-                    }
-                }
+    /*
+    Lets the cars of one cars_before_stop_* queue yield to an emergency vehicle coming up behind them, where they can.
+    A car in the curb lane of its approach pulls over once it has stopped with an emergency vehicle less than PREEMPTION_DETECTION_DISTANCE behind it in its lane, unless it stands closer to its stop line than ConflictMatrix::aside, where a long vehicle turning would sweep over the curb, or another car has pulled over beside it. It moves sideways at PULL_ASIDE_SPEED until it is a lane width out, clear of its lane, and waits there while the cars behind it drive by. It pulls back in once no emergency vehicle is coming up behind it and its lane is clear: no car ahead within its minimum gap and none behind that could not stop short of it.
+    Cars in the other lanes have nowhere to go and are passed only as the cars ahead of the emergency vehicle drive through on its green.
+    As cars drive past the ones pulled over, the queue is put back in the order of how far the cars have travelled.
+    */
+    fn pull_aside(&self, side: Side, queue: &mut [Car]) {
+        let curb = self.geometry.lanes - 1;
+        let step = PULL_ASIDE_SPEED * SIMULATION_STEP;
+        for i in 0..queue.len() {
+            let car = &queue[i];
+            if car.trajectory.lane != curb || car.is_emergency() {
+                continue;
             }
-            if self.cars_before_stop_north[0].y >= (OUTPUT_HEIGHT / 2 - 2 * CAR_HEIGHT)
-                && self.north_lights.color == Light::Green
-            {
-                if self.cars_in_intersection.len() == 0 {
-                    self.cars_in_intersection
-                        .push(self.cars_before_stop_north[0].clone());
-                    self.cars_before_stop_north.remove(0);
+            let coming = queue.iter().any(|other| {
+                other.is_emergency()
+                    && other.trajectory.lane == curb
+                    && other.travelled < car.travelled
+                    && car.travelled - other.travelled <= PREEMPTION_DETECTION_DISTANCE as f64
+            });
+            let lane_width = car.trajectory.lane_width;
+            let aside = if coming {
+                let room = car.trajectory.stop_line - car.travelled >= self.conflicts.aside(side)
+                    && curb_clear(queue, i);
+                if car.aside > 0.0 || (room && car.speed <= STOPPED_SPEED) {
+                    (car.aside + step).min(lane_width)
+                } else {
+                    0.0
                 }
+            } else if car.aside == 0.0 {
+                0.0
+            } else if car.aside < lane_width || lane_clear(queue, i, &self.cars_in_intersection) {
+                (car.aside - step).max(0.0)
+            } else {
+                lane_width
+            };
+            if aside > 0.0 {
+                queue[i].speed = 0.0;
             }
+            queue[i].aside = aside;
         }
+        if queue.iter().any(|car| car.aside > 0.0) {
+            queue.sort_by(|a, b| b.travelled.total_cmp(&a.travelled));
+        }
+    }
 
-        if self.cars_before_stop_south.len() > 0 {
-            if self.cars_before_stop_south[0].y > (OUTPUT_HEIGHT / 2 + 2 * CAR_HEIGHT) {
-                self.cars_before_stop_south[0].moove(self.south_lights.clone());
-
-                let mut front_car_y = self.cars_before_stop_south[0].y;
-                for i in 1..self.cars_before_stop_south.len() {
-                    if self.cars_before_stop_south[i].y - self.cars_before_stop_south[i].velocity
-                        > front_car_y + SECURITY_DISTANCE
-                    {
-                        self.cars_before_stop_south[i].moove(self.south_lights.clone());
-                        front_car_y = self.cars_before_stop_south[i].y;
-                    }
-                }
-            }
-            if self.cars_before_stop_south[0].y <= (OUTPUT_HEIGHT / 2 + 2 * CAR_HEIGHT)
-                && self.south_lights.color == Light::Green
+    /*
+    Sets how far short of its stop line each car of one cars_before_stop_* queue waits: the longest of its ConflictMatrix::hold for the vehicles of other sides on their way, entering the road, on their approaches or in the intersection, which is 0 unless a long vehicle turns over the approach. A car takes a longer hold only while it can still stop short of it; one that cannot drives on to its line, and the long vehicle waits for it in sweep_clear_for.
+    */
+    fn hold_back(&self, side: Side, queue: &mut [Car]) {
+        let sweeping: Vec<(VehicleClass, Movement)> = Side::ALL
+            .into_iter()
+            .filter(|&other| other != side)
+            .flat_map(|other| self.cars_before_stop(other))
+            .chain(&self.entering)
+            .chain(&self.cars_in_intersection)
+            .filter(|car| car.side != side)
+            .map(|car| (car.class, car.movement()))
+            .collect();
+        for car in queue.iter_mut() {
+            let movement = car.movement();
+            let hold = sweeping
+                .iter()
+                .map(|(class, driving)| self.conflicts.hold(*class, driving, &movement))
+                .fold(0.0, f64::max);
+            if hold <= car.trajectory.hold
+                || car.travelled + car.idm.stopping_distance(car.speed)
+                    <= car.trajectory.stop_line - hold
             {
-                if self.cars_in_intersection.len() == 0 {
-                    self.cars_in_intersection
-                        .push(self.cars_before_stop_south[0].clone());
-                    self.cars_before_stop_south.remove(0);
-                }
+                car.trajectory.hold = hold;
             }
         }
+    }
 
-        if self.cars_before_stop_east.len() > 0 {
-            if self.cars_before_stop_east[0].x < (OUTPUT_WIDTH / 2 - 2 * CAR_WIDTH) {
-                self.cars_before_stop_east[0].moove(self.east_lights.clone());
+    /*
+    Moves the cars of one cars_before_stop_* queue with the car-following model.
 
-                let mut front_car_x = self.cars_before_stop_east[0].x;
-                for i in 1..self.cars_before_stop_east.len() {
-                    if self.cars_before_stop_east[i].x + self.cars_before_stop_east[i].velocity
-                        < front_car_x - SECURITY_DISTANCE
-                    {
-                        self.cars_before_stop_east[i].moove(self.east_lights.clone());
-                        front_car_x = self.cars_before_stop_east[i].x;
-                    }
+    Every car follows the car in front of it in its lane. The first car of each lane follows the last car of its side and lane that went into the intersection, and also treats its stop line as a standing obstacle whenever the gate would not let it through, so it brakes smoothly to a halt at the line.
+    Once at the stop line the first car of a lane enters the intersection when the gate lets it through, and is marked as waiting otherwise.
+    With signals the gate is the traffic light plus the conflict check against the cars already inside. With an IntersectionManager the gate is the car's reservation: the first car of a lane asks for one once it is within RESERVATION_REQUEST_DISTANCE of the line, slows down for the line until it gets one, and gives it up to ask again when it falls behind the course it was planned on. It always follows the last car of its lane that went in, so a car slowed down in the intersection holds up the cars behind it rather than being run into.
+    */
+    fn advance_queue(&mut self, side: Side, queue: &mut Vec<Car>) {
+        let light = match self.manager {
+            Some(_) => TrafficLight::new(Light::Green),
+            None => self.lights(side).clone(),
+        };
+        let now = self.tick;
+        self.pull_aside(side, queue);
+        self.hold_back(side, queue);
+        for i in 0..queue.len() {
+            if queue[i].aside > 0.0 {
+                continue;
+            }
+            if let Some(ahead) = lane_ahead(queue, i) {
+                let leader = Some((queue[i].gap_to(&queue[ahead]), queue[ahead].speed));
+                queue[i].follow(leader, light.clone());
+                continue;
+            }
+            let mut reserved = true;
+            if let Some(manager) = self.manager.as_mut() {
+                let head = &queue[i];
+                if let Some(held) = manager.reservation(head.id)
+                    && held.is_behind(head, now)
+                {
+                    manager.cancel(head.id);
                 }
+                reserved = head.distance_to_stop_line() <= RESERVATION_REQUEST_DISTANCE as f64
+                    && manager.request(head, &self.geometry, now);
             }
-            if self.cars_before_stop_east[0].x >= (OUTPUT_WIDTH / 2 - 2 * CAR_WIDTH)
-                && self.east_lights.color == Light::Green
+            let head = &queue[i];
+            let open = match &self.manager {
+                Some(_) => reserved,
+                None => head.may_pass(&light) && self.intersection_clear_for(head),
+            };
+            let mut leader = None;
+            if let Some(ahead) = self
+                .cars_in_intersection
+                .iter()
+                .rev()
+                .find(|car| car.side == side && car.trajectory.lane == head.trajectory.lane)
             {
-                if self.cars_in_intersection.len() == 0 {
-                    self.cars_in_intersection
-                        .push(self.cars_before_stop_east[0].clone());
-                    self.cars_before_stop_east.remove(0);
+                let gap = ahead.travelled - ahead.length as f64 - head.travelled;
+                leader = Some((gap, ahead.speed));
+            }
+            if !open {
+                let gap = head.distance_to_stop_line().max(0.0) + head.idm.min_gap;
+                if leader.is_none_or(|(ahead, _)| gap < ahead) {
+                    leader = Some((gap, 0.0));
                 }
             }
+            queue[i].follow(leader, light.clone());
         }
 
-        if self.cars_before_stop_west.len() > 0 {
-            if self.cars_before_stop_west[0].x > (OUTPUT_WIDTH / 2 + CAR_WIDTH) {
-                self.cars_before_stop_west[0].moove(self.west_lights.clone());
-
-                let mut front_car_x = self.cars_before_stop_west[0].x;
-                for i in 1..self.cars_before_stop_west.len() {
-                    if self.cars_before_stop_west[i].x - self.cars_before_stop_west[i].velocity
-                        > front_car_x + SECURITY_DISTANCE
-                    {
-                        self.cars_before_stop_west[i].moove(self.west_lights.clone());
-                        front_car_x = self.cars_before_stop_west[i].x;
+        let heads: Vec<u64> = (0..queue.len())
+            .filter(|&i| queue[i].aside == 0.0 && lane_ahead(queue, i).is_none())
+            .map(|i| queue[i].id)
+            .collect();
+        for id in heads {
+            let Some(i) = queue.iter().position(|car| car.id == id) else {
+                continue;
+            };
+            if queue[i].at_stop_line() {
+                let may_enter = match &self.manager {
+                    Some(manager) => {
+                        manager.may_enter(&queue[i], now) && self.sweep_clear_for(&queue[i])
+                    }
+                    None => queue[i].may_pass(&light) && self.intersection_clear_for(&queue[i]),
+                };
+                if may_enter {
+                    if let Some(manager) = self.manager.as_mut() {
+                        manager.enter(queue[i].id);
                     }
+                    self.cars_in_intersection.push(queue.remove(i));
+                } else {
+                    queue[i].waiting = true;
                 }
             }
-            if self.cars_before_stop_west[0].x <= (OUTPUT_WIDTH / 2 + CAR_WIDTH)
-                && self.west_lights.color == Light::Green
-            {
-                if self.cars_in_intersection.len() == 0 {
-                    self.cars_in_intersection
-                        .push(self.cars_before_stop_west[0].clone());
-                    self.cars_before_stop_west.remove(0);
-                }
+        }
+    }
+}
+
+/*
+Returns the position of the car ahead of the car at position i of a queue in the same lane, the last one before it that is in its lane, or None when it is the first car of its lane.
+*/
+fn lane_ahead(queue: &[Car], i: usize) -> Option<usize> {
+    let lane = queue[i].trajectory.lane;
+    queue[..i]
+        .iter()
+        .rposition(|car| car.trajectory.lane == lane && car.in_lane())
+}
+
+/*
+Returns whether the car at position i of a queue, pulled over to the curb, may move back into its lane: no car in the lane, in the queue or already in the intersection, is ahead of it within its minimum gap, and none is behind it closer than its own minimum gap and stopping distance.
+*/
+fn lane_clear(queue: &[Car], i: usize, inside: &[Car]) -> bool {
+    let car = &queue[i];
+    let others = queue[..i]
+        .iter()
+        .chain(&queue[i + 1..])
+        .chain(inside.iter().filter(|other| other.side == car.side));
+    others.into_iter().all(|other| {
+        if other.trajectory.lane != car.trajectory.lane || !other.in_lane() {
+            true
+        } else if other.travelled >= car.travelled {
+            other.travelled - other.length as f64 - car.travelled >= car.idm.min_gap
+        } else {
+            car.travelled - car.length as f64 - other.travelled
+                >= other.idm.min_gap + other.idm.stopping_distance(other.speed)
+        }
+    })
+}
+
+/*
+Returns whether the car at position i of a queue may pull over to the curb: no other car of its lane that has pulled over, even partly, is beside it or within its minimum gap.
+*/
+fn curb_clear(queue: &[Car], i: usize) -> bool {
+    let car = &queue[i];
+    queue.iter().enumerate().all(|(j, other)| {
+        j == i
+            || other.trajectory.lane != car.trajectory.lane
+            || other.aside == 0.0
+            || other.travelled - other.length as f64 - car.travelled >= car.idm.min_gap
+            || car.travelled - car.length as f64 - other.travelled >= car.idm.min_gap
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::super::scenario::*;
+    use super::*;
+
+    /*
+    Returns a vehicle of the given class stopped the given distance along the curb lane of the north approach.
+    */
+    fn stopped(road: &Road, class: VehicleClass, travelled: f64) -> Car {
+        let mut car = Car::with_speed(Side::FromNorth, Direction::Straight, class, 150.0);
+        car.route(&road.geometry);
+        car.moove(travelled, TrafficLight::new(Light::Green));
+        car.speed = 0.0;
+        car
+    }
+
+    #[test]
+    fn queued_cars_pull_over_for_an_emergency_vehicle() {
+        let road = Road::new();
+        let mut queue = vec![
+            stopped(&road, VehicleClass::PassengerCar, 250.0),
+            stopped(&road, VehicleClass::Van, 200.0),
+            stopped(&road, VehicleClass::Emergency, 120.0),
+        ];
+        for _ in 0..2 * TICKS_PER_SECOND {
+            road.pull_aside(Side::FromNorth, &mut queue);
+        }
+        assert_eq!(queue[0].aside, road.geometry.lane_width as f64);
+        assert_eq!(queue[1].aside, road.geometry.lane_width as f64);
+        assert_eq!(lane_ahead(&queue, 2), None);
+        for (i, car) in queue.iter().enumerate() {
+            for other in &queue[i + 1..] {
+                assert!(!polygons_overlap(&car.body(), &other.body()));
             }
         }
+
+        queue[2].moove(180.0, TrafficLight::new(Light::Green));
+        for _ in 0..2 * TICKS_PER_SECOND {
+            road.pull_aside(Side::FromNorth, &mut queue);
+        }
+        assert!(queue[0].is_emergency());
+        assert!(queue.iter().all(|car| car.aside == 0.0));
+        assert_eq!(lane_ahead(&queue, 1), Some(0));
+    }
+
+    #[test]
+    fn cars_by_the_stop_line_stay_in_their_lane() {
+        let road = Road::new();
+        let stop_line = stopped(&road, VehicleClass::PassengerCar, 0.0)
+            .trajectory
+            .stop_line;
+        let at_line = stop_line - road.conflicts.aside(Side::FromNorth) + 1.0;
+        let mut queue = vec![
+            stopped(&road, VehicleClass::PassengerCar, at_line),
+            stopped(&road, VehicleClass::Emergency, at_line - 60.0),
+        ];
+        for _ in 0..2 * TICKS_PER_SECOND {
+            road.pull_aside(Side::FromNorth, &mut queue);
+        }
+        assert_eq!(queue[0].aside, 0.0);
+        assert_eq!(lane_ahead(&queue, 1), Some(0));
+    }
+
+    #[test]
+    fn cars_enter_alongside_the_movements_they_do_not_conflict_with() {
+        let mut road = Road::new();
+        let entering = |side, direction| {
+            let mut car = Car::with_speed(side, direction, VehicleClass::PassengerCar, 150.0);
+            car.route(&road.geometry);
+            car.moove(car.trajectory.stop_line, TrafficLight::new(Light::Green));
+            car
+        };
+        let inside = entering(Side::FromNorth, Direction::Straight);
+        let opposite = entering(Side::FromSouth, Direction::Straight);
+        let crossing = entering(Side::FromEast, Direction::Straight);
+        let behind = entering(Side::FromNorth, Direction::Straight);
+        road.cars_in_intersection.push(inside);
+        assert!(road.intersection_clear_for(&opposite));
+        assert!(!road.intersection_clear_for(&crossing));
+        assert!(!road.intersection_clear_for(&behind));
+        road.cars_in_intersection.push(opposite);
+        road.cars_in_intersection[0].moove(200.0, TrafficLight::new(Light::Green));
+        assert!(road.intersection_clear_for(&behind));
+        assert!(!road.intersection_clear_for(&crossing));
+    }
+
+    #[test]
+    fn turning_cars_yield_to_pedestrians_on_their_crosswalks() {
+        let mut road = Road::new();
+        let mut car = Car::with_speed(
+            Side::FromNorth,
+            Direction::Right,
+            VehicleClass::PassengerCar,
+            150.0,
+        );
+        car.route(&road.geometry);
+        assert!(road.intersection_clear_for(&car));
+
+        let mut pedestrian = Pedestrian::new(car.movement().exit_leg(), true, 0);
+        road.spawn_pedestrian(pedestrian.clone());
+        assert!(road.intersection_clear_for(&car));
+        road.pedestrian_lights[car.movement().exit_leg().index()] = PedestrianLight::Walk;
+        assert!(!road.intersection_clear_for(&car));
+
+        road.pedestrians.clear();
+        road.pedestrian_lights = [PedestrianLight::DontWalk; 4];
+        pedestrian.crossing = true;
+        road.spawn_pedestrian(pedestrian);
+        assert!(!road.intersection_clear_for(&car));
+
+        road.pedestrians.clear();
+        let exit = car.movement().exit_leg();
+        road.cars_in_intersection.push(car);
+        assert!(!road.crosswalk_clear(Side::FromNorth));
+        assert!(!road.crosswalk_clear(exit));
+        assert!(road.crosswalk_clear(Side::FromSouth));
+    }
+
+    /*
+    Runs two minutes of the morning peak scenario from the given seed and returns the trips recorded and where every vehicle on the road ended up.
+    */
+    fn run(seed: u64) -> (StatsCollector, Vec<(u64, f64, f64)>) {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios/am_peak.toml");
+        let mut road = Scenario::load(&path).unwrap().road().unwrap();
+        road.reseed(seed);
+        for _ in 0..120 * TICKS_PER_SECOND {
+            road.simulation_loop();
+        }
+        let car = road.generate_car(Side::FromWest);
+        road.spawn(car);
+        let mut cars = road.entering.clone();
+        cars.extend(road.cars_in_intersection.iter().cloned());
+        for side in Side::ALL {
+            cars.extend(road.cars_before_stop(side).iter().cloned());
+            cars.extend(road.cars_after_stop(side).iter().cloned());
+        }
+        let positions = cars.iter().map(|car| (car.id, car.x, car.y)).collect();
+        (road.collector, positions)
+    }
+
+    #[test]
+    fn the_same_seed_gives_the_same_run() {
+        let first = run(11);
+        assert!(!first.0.finished.is_empty());
+        assert_eq!(run(11), first);
+        assert_ne!(run(12), first);
+    }
+
+    /*
+    Returns a vehicle of the given class at the start of the east approach, about to turn left, which takes it over the north approach.
+    */
+    fn turning(road: &Road, class: VehicleClass) -> Car {
+        let mut car = Car::with_speed(Side::FromEast, Direction::Left, class, 150.0);
+        car.route(&road.geometry);
+        car
+    }
+
+    #[test]
+    fn cars_hold_back_only_for_long_vehicles_turning_over_them() {
+        let mut road = Road::new();
+        let mut queue = vec![stopped(&road, VehicleClass::PassengerCar, 100.0)];
+        road.hold_back(Side::FromNorth, &mut queue);
+        assert_eq!(queue[0].trajectory.hold, 0.0);
+
+        let car = turning(&road, VehicleClass::PassengerCar);
+        road.cars_before_stop_mut(Side::FromEast).push(car);
+        road.hold_back(Side::FromNorth, &mut queue);
+        assert_eq!(queue[0].trajectory.hold, 0.0);
+
+        let bus = turning(&road, VehicleClass::ArticulatedBus);
+        let hold = road
+            .conflicts
+            .hold(bus.class, &bus.movement(), &queue[0].movement());
+        assert!(hold > 0.0);
+        road.cars_before_stop_mut(Side::FromEast).push(bus);
+        road.hold_back(Side::FromNorth, &mut queue);
+        assert_eq!(queue[0].trajectory.hold, hold);
+
+        road.cars_before_stop_mut(Side::FromEast).pop();
+        road.hold_back(Side::FromNorth, &mut queue);
+        assert_eq!(queue[0].trajectory.hold, 0.0);
+    }
+
+    #[test]
+    fn long_vehicles_wait_for_the_cars_in_their_way() {
+        let mut road = Road::new();
+        let car = stopped(&road, VehicleClass::PassengerCar, 0.0);
+        let stop_line = car.trajectory.stop_line;
+        let waiting = stopped(&road, VehicleClass::PassengerCar, stop_line);
+        road.cars_before_stop_mut(Side::FromNorth).push(waiting);
+        assert!(road.sweep_clear_for(&turning(&road, VehicleClass::PassengerCar)));
+        assert!(!road.sweep_clear_for(&turning(&road, VehicleClass::ArticulatedBus)));
+
+        road.spawn(turning(&road, VehicleClass::ArticulatedBus));
+        assert_eq!(road.entering.len(), 1);
+        assert!(road.cars_before_stop(Side::FromEast).is_empty());
+
+        road.cars_before_stop_mut(Side::FromNorth).clear();
+        road.admit();
+        assert!(road.entering.is_empty());
+        assert_eq!(road.cars_before_stop(Side::FromEast).len(), 1);
     }
 }
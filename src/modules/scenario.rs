@@ -0,0 +1,838 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::constants::*;
+use super::controller::*;
+use super::demand::*;
+use super::fixed_time::*;
+use super::geometry::*;
+use super::preemption::*;
+use super::reservation::*;
+use super::road::*;
+use super::se_base::*;
+use super::speeds::*;
+use super::timing::*;
+use super::turning::*;
+use super::vehicle::*;
+
+/*
+A Scenario describes a whole run: the intersection layout, the vehicle mix, the demand and turning movements of each approach, the signal controller and its parameters, the seed and the run length. It is read from a TOML file, or from the equivalent JSON file, and every section may be left out to keep its default.
+Times are in seconds and flows in vehicles per hour, converted to ticks when the Road is built:
+
+seed: the seed of the Road's generator, random when not given
+duration: the simulated seconds to run before stopping, forever when not given
+layout: the geometry of the intersection and the pedestrian demand
+mix: the shares of the vehicle classes
+speeds: the distribution of the desired speeds of the vehicles
+demand: the arrivals of each approach
+od: an origin-destination matrix over the four legs, instead of the turning shares of the approaches
+signals: the controller that runs the intersection
+*/
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scenario {
+    pub seed: Option<u64>,
+    pub duration: Option<f64>,
+    pub layout: LayoutConfig,
+    pub mix: MixConfig,
+    pub speeds: SpeedConfig,
+    pub demand: Vec<ApproachConfig>,
+    pub od: Option<[[f64; 4]; 4]>,
+    pub signals: SignalConfig,
+}
+
+/*
+The layout of the intersection, as in Geometry: width and height are the size of the canvas, lane_width the width of a lane and lanes the number of lanes in each direction, setback the distance from the stop line to the intersection box and approach the length of the road up to the stop line, all in pixels. pedestrians is the pedestrian demand of each crosswalk in pedestrians per hour.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    pub width: i32,
+    pub height: i32,
+    pub lane_width: i32,
+    pub lanes: i32,
+    pub setback: i32,
+    pub approach: i32,
+    pub pedestrians: f64,
+}
+
+impl Default for LayoutConfig {
+    fn default() -> LayoutConfig {
+        let geometry = Geometry::default();
+        LayoutConfig {
+            width: geometry.width,
+            height: geometry.height,
+            lane_width: geometry.lane_width,
+            lanes: geometry.lanes,
+            setback: geometry.setback,
+            approach: geometry.approach,
+            pedestrians: 0.0,
+        }
+    }
+}
+
+/*
+The weights of the regular vehicle classes, as in TrafficMix.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MixConfig {
+    pub passenger_car: f64,
+    pub van: f64,
+    pub truck: f64,
+    pub articulated_bus: f64,
+    pub motorcycle: f64,
+}
+
+impl Default for MixConfig {
+    fn default() -> MixConfig {
+        let [passenger_car, van, truck, articulated_bus, motorcycle] = TrafficMix::default().shares;
+        MixConfig {
+            passenger_car,
+            van,
+            truck,
+            articulated_bus,
+            motorcycle,
+        }
+    }
+}
+
+/*
+The desired speeds of the generated vehicles, in pixels per second, as in SpeedDistribution:
+
+distribution: the shape of the distribution, as a DistributionKind
+min and max: the range of uniform and normal speeds
+mean and sd: the mean and standard deviation of normal speeds
+speed: the speed of every vehicle with a fixed distribution
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeedConfig {
+    pub distribution: DistributionKind,
+    pub min: f64,
+    pub max: f64,
+    pub mean: Option<f64>,
+    pub sd: Option<f64>,
+    pub speed: Option<f64>,
+}
+
+/*
+The shape of a SpeedDistribution: Uniform, the default, Normal or Fixed.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DistributionKind {
+    #[default]
+    Uniform,
+    Normal,
+    Fixed,
+}
+
+impl Default for SpeedConfig {
+    fn default() -> SpeedConfig {
+        SpeedConfig {
+            distribution: DistributionKind::Uniform,
+            min: MIN_SPEED,
+            max: MAX_SPEED,
+            mean: None,
+            sd: None,
+            speed: None,
+        }
+    }
+}
+
+/*
+The demand of one approach:
+
+side: the approach, from_north, from_east, from_south or from_west
+flow: a constant flow rate in vehicles per hour, or
+profile: a piecewise linear flow rate, points of at (seconds) and flow (vehicles per hour)
+headway: the distribution of the headways, as a HeadwayKind
+min_headway: the minimum headway in seconds of a shifted_exponential headway
+turning: the left, straight and right shares of the approach
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApproachConfig {
+    pub side: Side,
+    #[serde(default)]
+    pub flow: Option<f64>,
+    #[serde(default)]
+    pub profile: Vec<FlowPointConfig>,
+    #[serde(default)]
+    pub headway: HeadwayKind,
+    #[serde(default)]
+    pub min_headway: Option<f64>,
+    #[serde(default)]
+    pub turning: Option<TurningConfig>,
+}
+
+/*
+The Headway of an approach: Poisson, the default, ShiftedExponential or Uniform.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeadwayKind {
+    #[default]
+    Poisson,
+    ShiftedExponential,
+    Uniform,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FlowPointConfig {
+    pub at: f64,
+    pub flow: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TurningConfig {
+    pub left: f64,
+    pub straight: f64,
+    pub right: f64,
+}
+
+/*
+The signal controller and its parameters, all times in seconds:
+
+controller: the controller that runs the intersection, as a ControllerKind
+min_green, green, amber and all_red: the SignalTiming of longest_queue
+green_north, green_east, green_south and green_west: the longest green of one side under longest_queue, green when left out
+phases, offset, amber and all_red: the SignalPlan of fixed_time, whose cycle is the sum of the splits
+schedule: the plans fixed_time changes to later in the run, each with the same amber and all_red, as in FixedTime::with_schedule
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignalConfig {
+    pub controller: ControllerKind,
+    pub min_green: f64,
+    pub green: f64,
+    pub green_north: Option<f64>,
+    pub green_east: Option<f64>,
+    pub green_south: Option<f64>,
+    pub green_west: Option<f64>,
+    pub amber: f64,
+    pub all_red: f64,
+    pub phases: Vec<PhaseConfig>,
+    pub offset: f64,
+    pub schedule: Vec<PlanConfig>,
+}
+
+impl Default for SignalConfig {
+    fn default() -> SignalConfig {
+        let seconds = |ticks: u32| ticks as f64 / TICKS_PER_SECOND as f64;
+        SignalConfig {
+            controller: ControllerKind::LongestQueue,
+            min_green: seconds(MIN_GREEN_TICKS),
+            green: seconds(GREEN_TICKS),
+            green_north: None,
+            green_east: None,
+            green_south: None,
+            green_west: None,
+            amber: seconds(AMBER_TICKS),
+            all_red: seconds(ALL_RED_TICKS),
+            phases: vec![],
+            offset: 0.0,
+            schedule: vec![],
+        }
+    }
+}
+
+/*
+The controller that runs the intersection: LongestQueue, the default, FixedTime, or an IntersectionManager granting Reservations instead of lights.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ControllerKind {
+    #[default]
+    LongestQueue,
+    FixedTime,
+    Reservations,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhaseConfig {
+    pub side: Side,
+    pub split: f64,
+}
+
+/*
+A plan of the fixed_time schedule: its phases and offset, running from at seconds into the run.
+*/
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanConfig {
+    pub at: f64,
+    pub phases: Vec<PhaseConfig>,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+impl Scenario {
+    /*
+    Reads a scenario from a file, as JSON when its extension is .json and as TOML otherwise, and validates it.
+    */
+    pub fn load(path: &Path) -> Result<Scenario, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let scenario = if path
+            .extension()
+            .is_some_and(|extension| extension == "json")
+        {
+            Scenario::from_json(&text)
+        } else {
+            Scenario::from_toml(&text)
+        };
+        scenario.map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /*
+    Parses and validates a TOML scenario. A field that cannot be read is reported with its path, like a validation error.
+    */
+    pub fn from_toml(text: &str) -> Result<Scenario, String> {
+        let scenario: Scenario = serde_path_to_error::deserialize(toml::Deserializer::new(text))
+            .map_err(|err| parse_error(err.path(), err.inner().message()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    pub fn from_json(text: &str) -> Result<Scenario, String> {
+        let mut deserializer = serde_json::Deserializer::from_str(text);
+        let scenario: Scenario = serde_path_to_error::deserialize(&mut deserializer)
+            .map_err(|err| parse_error(err.path(), &err.inner().to_string()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /*
+    Checks the whole scenario and reports the first problem found, prefixed with the path of the offending field.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(duration) = self.duration {
+            seconds("duration", duration)?;
+        }
+        self.layout
+            .validate()
+            .map_err(|err| format!("layout.{}", err))?;
+        self.mix.validate().map_err(|err| format!("mix.{}", err))?;
+        self.speeds
+            .validate()
+            .map_err(|err| format!("speeds.{}", err))?;
+        let mut seen = vec![];
+        for (i, approach) in self.demand.iter().enumerate() {
+            let field = format!("demand[{}]", i);
+            approach
+                .validate()
+                .map_err(|err| format!("{}.{}", field, err))?;
+            if seen.contains(&approach.side) {
+                return Err(format!(
+                    "{}.side: another approach has the same side",
+                    field
+                ));
+            }
+            if self.od.is_some() && approach.turning.is_some() {
+                return Err(format!(
+                    "{}.turning: the turning shares come from od, leave them out here",
+                    field
+                ));
+            }
+            seen.push(approach.side);
+        }
+        if let Some(od) = &self.od {
+            TurningProportions::validate_od(od)?;
+            TurningProportions::from_od(*od)
+                .validate()
+                .map_err(|_| "od: every origin needs at least one positive share".to_string())?;
+        }
+        self.signals
+            .validate()
+            .map_err(|err| format!("signals.{}", err))?;
+        if self.signals.controller == ControllerKind::Reservations && self.layout.pedestrians > 0.0
+        {
+            return Err(format!(
+                "layout.pedestrians: {} pedestrians per hour would never cross, reservations have no pedestrian phase",
+                self.layout.pedestrians
+            ));
+        }
+        Ok(())
+    }
+
+    /*
+    Returns the run length in ticks, if the scenario has one.
+    */
+    pub fn duration_ticks(&self) -> Option<u64> {
+        self.duration.map(|duration| ticks(duration) as u64)
+    }
+
+    pub fn demand(&self) -> Demand {
+        Demand {
+            approaches: self
+                .demand
+                .iter()
+                .map(|approach| approach.demand())
+                .collect(),
+        }
+    }
+
+    pub fn turning(&self) -> TurningProportions {
+        if let Some(od) = self.od {
+            return TurningProportions::from_od(od);
+        }
+        let mut turning = TurningProportions::default();
+        for approach in &self.demand {
+            if let Some(shares) = approach.turning {
+                turning =
+                    turning.with_side(approach.side, [shares.left, shares.straight, shares.right]);
+            }
+        }
+        turning
+    }
+
+    /*
+    Validates the scenario and builds the Road it describes, with its geometry, controller or IntersectionManager, seed, traffic mix, speed distribution, demand, turning proportions and pedestrian demand.
+    */
+    pub fn road(&self) -> Result<Road, String> {
+        self.validate()?;
+        let mut road = self.signals.road()?;
+        if let Some(seed) = self.seed {
+            road.reseed(seed);
+        }
+        road.set_geometry(self.layout.geometry());
+        road.mix = self.mix.mix();
+        road.speeds = self.speeds.distribution();
+        road.demand = self.demand();
+        road.turning = self.turning();
+        road.pedestrian_demand = self.layout.pedestrians;
+        Ok(road)
+    }
+}
+
+impl LayoutConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        self.geometry().validate()?;
+        rate("pedestrians", self.pedestrians)
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        Geometry {
+            width: self.width,
+            height: self.height,
+            lane_width: self.lane_width,
+            lanes: self.lanes,
+            setback: self.setback,
+            approach: self.approach,
+        }
+    }
+}
+
+impl MixConfig {
+    /*
+    Checks that no share is negative or infinite and that at least one is positive.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        for (field, share) in [
+            ("passenger_car", self.passenger_car),
+            ("van", self.van),
+            ("truck", self.truck),
+            ("articulated_bus", self.articulated_bus),
+            ("motorcycle", self.motorcycle),
+        ] {
+            rate(field, share)?;
+        }
+        if self.mix().shares.iter().sum::<f64>() <= 0.0 {
+            return Err("passenger_car: at least one class needs a positive share".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn mix(&self) -> TrafficMix {
+        TrafficMix {
+            shares: [
+                self.passenger_car,
+                self.van,
+                self.truck,
+                self.articulated_bus,
+                self.motorcycle,
+            ],
+        }
+    }
+}
+
+impl SpeedConfig {
+    /*
+    Checks that the fields match the distribution, then the distribution itself.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        let (name, needed): (&str, &[&str]) = match self.distribution {
+            DistributionKind::Uniform => ("uniform", &[]),
+            DistributionKind::Normal => ("normal", &["mean", "sd"]),
+            DistributionKind::Fixed => ("fixed", &["speed"]),
+        };
+        for (field, value) in [("mean", self.mean), ("sd", self.sd), ("speed", self.speed)] {
+            match (needed.contains(&field), value.is_some()) {
+                (true, false) => {
+                    return Err(format!(
+                        "{}: a {} distribution needs a {}",
+                        field, name, field
+                    ));
+                }
+                (false, true) => {
+                    return Err(format!(
+                        "{}: a {} distribution has no {}",
+                        field, name, field
+                    ));
+                }
+                _ => {}
+            }
+        }
+        self.distribution().validate()
+    }
+
+    pub fn distribution(&self) -> SpeedDistribution {
+        match self.distribution {
+            DistributionKind::Normal => SpeedDistribution::Normal {
+                mean: self.mean.unwrap_or_default(),
+                sd: self.sd.unwrap_or_default(),
+                min: self.min,
+                max: self.max,
+            },
+            DistributionKind::Fixed => SpeedDistribution::Fixed {
+                speed: self.speed.unwrap_or_default(),
+            },
+            DistributionKind::Uniform => SpeedDistribution::Uniform {
+                min: self.min,
+                max: self.max,
+            },
+        }
+    }
+}
+
+impl ApproachConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        match (self.flow, self.profile.is_empty()) {
+            (Some(flow), true) => rate("flow", flow)?,
+            (None, false) => {
+                for (i, point) in self.profile.iter().enumerate() {
+                    seconds(&format!("profile[{}].at", i), point.at)?;
+                    rate(&format!("profile[{}].flow", i), point.flow)?;
+                    if i > 0 && ticks(point.at) <= ticks(self.profile[i - 1].at) {
+                        return Err(format!(
+                            "profile[{}].at: {} seconds does not come after the previous point",
+                            i, point.at
+                        ));
+                    }
+                }
+            }
+            (Some(_), false) => {
+                return Err("flow: give either flow or profile, not both".to_string());
+            }
+            (None, true) => return Err("flow: a flow or a profile is required".to_string()),
+        }
+        match (self.headway, self.min_headway) {
+            (HeadwayKind::ShiftedExponential, Some(min_headway)) => {
+                seconds("min_headway", min_headway)?
+            }
+            (HeadwayKind::ShiftedExponential, None) => {
+                return Err(
+                    "min_headway: a shifted_exponential headway needs a minimum".to_string()
+                );
+            }
+            (_, None) => {}
+            (_, Some(_)) => {
+                return Err(
+                    "min_headway: only a shifted_exponential headway has a minimum".to_string(),
+                );
+            }
+        }
+        if let Some(turning) = &self.turning {
+            rate("turning.left", turning.left)?;
+            rate("turning.straight", turning.straight)?;
+            rate("turning.right", turning.right)?;
+            if turning.left + turning.straight + turning.right <= 0.0 {
+                return Err("turning: at least one share must be positive".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn profile(&self) -> DemandProfile {
+        match self.flow {
+            Some(flow) => DemandProfile::constant(flow),
+            None => DemandProfile::new(
+                self.profile
+                    .iter()
+                    .map(|point| FlowPoint {
+                        tick: ticks(point.at) as u64,
+                        flow: point.flow,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn headway(&self) -> Headway {
+        match self.headway {
+            HeadwayKind::ShiftedExponential => Headway::ShiftedExponential {
+                min_headway: self.min_headway.map_or(0.0, |min| ticks(min) as f64),
+            },
+            HeadwayKind::Uniform => Headway::Uniform,
+            HeadwayKind::Poisson => Headway::Poisson,
+        }
+    }
+
+    pub fn demand(&self) -> ApproachDemand {
+        ApproachDemand::new(self.side, self.profile(), self.headway())
+    }
+}
+
+impl SignalConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        seconds("amber", self.amber)?;
+        seconds("all_red", self.all_red)?;
+        match self.controller {
+            ControllerKind::LongestQueue => {
+                seconds("min_green", self.min_green)?;
+                for (field, green) in [
+                    ("green", Some(self.green)),
+                    ("green_north", self.green_north),
+                    ("green_east", self.green_east),
+                    ("green_south", self.green_south),
+                    ("green_west", self.green_west),
+                ] {
+                    if let Some(green) = green {
+                        seconds(field, green)?;
+                        if green < self.min_green {
+                            return Err(format!(
+                                "{}: {} seconds is shorter than min_green",
+                                field, green
+                            ));
+                        }
+                    }
+                }
+            }
+            ControllerKind::FixedTime => {
+                self.check_plan("", &self.phases, self.offset)?;
+                let mut previous = 0;
+                for (i, entry) in self.schedule.iter().enumerate() {
+                    let field = format!("schedule[{}]", i);
+                    seconds(&format!("{}.at", field), entry.at)?;
+                    if ticks(entry.at) <= previous {
+                        return Err(format!(
+                            "{}.at: {} seconds does not come after the previous plan",
+                            field, entry.at
+                        ));
+                    }
+                    previous = ticks(entry.at);
+                    self.check_plan(&format!("{}.", field), &entry.phases, entry.offset)?;
+                }
+            }
+            ControllerKind::Reservations => {}
+        }
+        Ok(())
+    }
+
+    /*
+    Checks a fixed_time plan the way SignalPlan::validate does, in the seconds of the scenario: it has at least one phase, every split leaves some green after amber and all red, and the offset falls inside the cycle. prefix is the path of the plan.
+    */
+    fn check_plan(&self, prefix: &str, phases: &[PhaseConfig], offset: f64) -> Result<(), String> {
+        if phases.is_empty() {
+            return Err(format!("{}phases: a plan needs at least one phase", prefix));
+        }
+        let clearance = self.amber + self.all_red;
+        for (i, phase) in phases.iter().enumerate() {
+            let field = format!("{}phases[{}]", prefix, i);
+            seconds(&format!("{}.split", field), phase.split)?;
+            if ticks(phase.split) <= ticks(self.amber) + ticks(self.all_red) {
+                return Err(format!(
+                    "{}.split: {} seconds leaves no green after {} seconds of amber and all red",
+                    field, phase.split, clearance
+                ));
+            }
+        }
+        seconds(&format!("{}offset", prefix), offset)?;
+        let cycle: u32 = phases.iter().map(|phase| ticks(phase.split)).sum();
+        if ticks(offset) >= cycle {
+            return Err(format!(
+                "{}offset: {} seconds is not within the {} second cycle",
+                prefix,
+                offset,
+                phases.iter().map(|phase| phase.split).sum::<f64>()
+            ));
+        }
+        Ok(())
+    }
+
+    /*
+    Creates a Road run by the configured controller, or by an IntersectionManager for reservations, whose emergency vehicle preemption clears with the configured amber and all-red. A fixed_time schedule that cannot be run is reported as FixedTime::validate finds it.
+    */
+    pub fn road(&self) -> Result<Road, String> {
+        let mut road = match self.controller {
+            ControllerKind::FixedTime => {
+                Road::with_controller(FixedTime::with_schedule(self.schedule())?)
+            }
+            ControllerKind::Reservations => Road::with_manager(IntersectionManager::new()),
+            ControllerKind::LongestQueue => Road::with_controller(LongestQueue::new(self.timing())),
+        };
+        road.preemption = Preemption::new(&self.timing());
+        Ok(road)
+    }
+
+    pub fn timing(&self) -> SignalTiming {
+        SignalTiming {
+            min_green: ticks(self.min_green),
+            green: Side::ALL.map(|side| ticks(self.green_of(side))),
+            amber: ticks(self.amber),
+            all_red: ticks(self.all_red),
+        }
+    }
+
+    /*
+    Returns the longest green of the given side in seconds, its own or the common one.
+    */
+    pub fn green_of(&self, side: Side) -> f64 {
+        match side {
+            Side::FromNorth => self.green_north,
+            Side::FromEast => self.green_east,
+            Side::FromSouth => self.green_south,
+            Side::FromWest => self.green_west,
+        }
+        .unwrap_or(self.green)
+    }
+
+    /*
+    Returns the time-of-day schedule of fixed_time: the plan of phases and offset from the start, then the plans of schedule.
+    */
+    pub fn schedule(&self) -> Vec<ScheduledPlan> {
+        let mut schedule = vec![ScheduledPlan {
+            start: 0,
+            plan: self.plan(),
+        }];
+        for entry in &self.schedule {
+            schedule.push(ScheduledPlan {
+                start: ticks(entry.at) as u64,
+                plan: self.plan_of(&entry.phases, entry.offset),
+            });
+        }
+        schedule
+    }
+
+    pub fn plan(&self) -> SignalPlan {
+        self.plan_of(&self.phases, self.offset)
+    }
+
+    fn plan_of(&self, phases: &[PhaseConfig], offset: f64) -> SignalPlan {
+        let phases = phases
+            .iter()
+            .map(|phase| PhaseSplit {
+                side: phase.side,
+                split: ticks(phase.split),
+            })
+            .collect();
+        let mut plan = SignalPlan::new(phases, ticks(offset));
+        plan.amber = ticks(self.amber);
+        plan.all_red = ticks(self.all_red);
+        plan
+    }
+}
+
+fn parse_error(path: &serde_path_to_error::Path, message: &str) -> String {
+    match path.to_string().as_str() {
+        "." => message.to_string(),
+        path => format!("{}: {}", path, message),
+    }
+}
+
+fn ticks(seconds: f64) -> u32 {
+    (seconds * TICKS_PER_SECOND as f64).round() as u32
+}
+
+fn seconds(field: &str, value: f64) -> Result<(), String> {
+    if value >= 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{}: {} seconds is not a duration", field, value))
+    }
+}
+
+fn rate(field: &str, value: f64) -> Result<(), String> {
+    if value >= 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{}: {} is not a rate or share", field, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_bundled_scenarios_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+        for file in ["am_peak.toml", "two_lane.toml", "fixed_time.json"] {
+            let scenario = Scenario::load(&dir.join(file)).unwrap();
+            assert!(scenario.road().is_ok(), "{}", file);
+        }
+        let scenario = Scenario::load(&dir.join("fixed_time.json")).unwrap();
+        assert_eq!(scenario.signals.controller, ControllerKind::FixedTime);
+    }
+
+    #[test]
+    fn unknown_names_are_reported_with_their_path() {
+        let errors = [
+            (
+                "[signals]\ncontroller = \"smart\"",
+                "signals.controller: unknown variant `smart`",
+            ),
+            (
+                "[speeds]\ndistribution = \"gamma\"",
+                "speeds.distribution: unknown variant `gamma`",
+            ),
+            (
+                "[[demand]]\nside = \"from_nowhere\"\nflow = 300",
+                "demand[0].side: unknown variant `from_nowhere`",
+            ),
+            (
+                "[[demand]]\nside = \"from_north\"\nflow = 300\nheadway = \"steady\"",
+                "demand[0].headway: unknown variant `steady`",
+            ),
+        ];
+        for (text, error) in errors {
+            let message = Scenario::from_toml(text).unwrap_err();
+            assert!(message.starts_with(error), "{}", message);
+        }
+    }
+
+    #[test]
+    fn reservations_are_run_without_pedestrians() {
+        let text = "[signals]\ncontroller = \"reservations\"\n\n[layout]\npedestrians = 120";
+        let message = Scenario::from_toml(text).unwrap_err();
+        assert!(message.starts_with("layout.pedestrians: "), "{}", message);
+        let text = "[signals]\ncontroller = \"reservations\"\n\n[layout]\npedestrians = 0";
+        assert!(Scenario::from_toml(text).is_ok());
+    }
+
+    #[test]
+    fn approaches_keep_their_side_and_headway() {
+        let text = "[[demand]]\nside = \"from_west\"\nflow = 300\nheadway = \"shifted_exponential\"\nmin_headway = 2\nturning = { left = 0, straight = 1, right = 0 }\n\n[[demand]]\nside = \"from_west\"\nflow = 100";
+        assert_eq!(
+            Scenario::from_toml(text).unwrap_err(),
+            "demand[1].side: another approach has the same side"
+        );
+        let scenario = Scenario::from_toml(&text[..text.find("\n\n").unwrap()]).unwrap();
+        let demand = scenario.demand();
+        assert_eq!(demand.approaches[0].side, Side::FromWest);
+        assert_eq!(
+            demand.approaches[0].headway,
+            Headway::ShiftedExponential { min_headway: 120.0 }
+        );
+        assert_eq!(
+            scenario.turning().shares[Side::FromWest.index()],
+            [0.0, 1.0, 0.0]
+        );
+    }
+}
@@ -1,11 +1,31 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Direction {
     Left,
     Right,
     Straight,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Direction {
+    pub const ALL: [Direction; 3] = [Direction::Left, Direction::Straight, Direction::Right];
+
+    /*
+    Returns a stable index for the direction, matching the order of Direction::ALL.
+    */
+    pub fn index(self) -> usize {
+        match self {
+            Direction::Left => 0,
+            Direction::Straight => 1,
+            Direction::Right => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Side {
     FromSouth,
     FromNorth,
@@ -13,17 +33,94 @@ pub enum Side {
     FromEast,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+impl Side {
+    pub const ALL: [Side; 4] = [
+        Side::FromNorth,
+        Side::FromEast,
+        Side::FromSouth,
+        Side::FromWest,
+    ];
+
+    /*
+    Returns a stable index for the side, matching the order of Side::ALL, so per-approach values can be kept in fixed size arrays.
+    */
+    pub fn index(self) -> usize {
+        match self {
+            Side::FromNorth => 0,
+            Side::FromEast => 1,
+            Side::FromSouth => 2,
+            Side::FromWest => 3,
+        }
+    }
+
+    /*
+    Picks one of the four sides with the given random number generator.
+    */
+    pub fn random<R: Rng + ?Sized>(rng: &mut R) -> Side {
+        Side::ALL[rng.random_range(0..Side::ALL.len())]
+    }
+
+    /*
+    Returns the side across the intersection from this one.
+    */
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::FromNorth => Side::FromSouth,
+            Side::FromEast => Side::FromWest,
+            Side::FromSouth => Side::FromNorth,
+            Side::FromWest => Side::FromEast,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Light {
     Green,
+    Amber,
     Red,
 }
 
 /*
-The TrafficLight struct represents a traffic light with a single attribute:
-color: an instance of the Light enum, which can take on values Green or Red.
+The TrafficLight struct represents a traffic light with the following attributes:
+color: an instance of the Light enum, which can take on values Green, Amber or Red.
+remaining: the number of ticks before the light changes, or 0 when the controller has not decided yet.
 */
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TrafficLight {
     pub color: Light,
+    pub remaining: u32,
+}
+
+impl TrafficLight {
+    pub fn new(color: Light) -> TrafficLight {
+        TrafficLight {
+            color,
+            remaining: 0,
+        }
+    }
+}
+
+/*
+The Rgb struct is a plain color value used by the simulation model, so that the core does not depend on any graphics library.
+When the render feature is enabled it converts into an sdl2 Color, which lets the canvas draw it directly.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Rgb {
+        Rgb { r, g, b }
+    }
+}
+
+#[cfg(feature = "render")]
+impl From<Rgb> for sdl2::pixels::Color {
+    fn from(rgb: Rgb) -> sdl2::pixels::Color {
+        sdl2::pixels::Color::RGB(rgb.r, rgb.g, rgb.b)
+    }
 }
@@ -0,0 +1,112 @@
+use rand::Rng;
+
+use super::constants::*;
+
+/*
+The distribution of the desired speed of the generated vehicles, in pixels per second. The class of a vehicle still caps its speed at the class's maximum.
+
+Uniform: speeds drawn uniformly between min and max
+Normal: speeds drawn from a normal distribution of the given mean and standard deviation sd, truncated to between min and max by drawing again
+Fixed: every vehicle wants the same speed
+
+The default is uniform between MIN_SPEED and MAX_SPEED.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpeedDistribution {
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    Normal {
+        mean: f64,
+        sd: f64,
+        min: f64,
+        max: f64,
+    },
+    Fixed {
+        speed: f64,
+    },
+}
+
+impl Default for SpeedDistribution {
+    fn default() -> SpeedDistribution {
+        SpeedDistribution::Uniform {
+            min: MIN_SPEED,
+            max: MAX_SPEED,
+        }
+    }
+}
+
+impl SpeedDistribution {
+    /*
+    Checks that every speed is positive and finite, that min is not above max, and that the mean of a normal distribution lies between them with a standard deviation that is not negative.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        let speed = |field: &str, value: f64| {
+            if value > 0.0 && value.is_finite() {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{}: {} pixels per second is not a speed",
+                    field, value
+                ))
+            }
+        };
+        match *self {
+            SpeedDistribution::Uniform { min, max } => {
+                speed("min", min)?;
+                speed("max", max)?;
+            }
+            SpeedDistribution::Normal { mean, sd, min, max } => {
+                speed("min", min)?;
+                speed("max", max)?;
+                speed("mean", mean)?;
+                if !(sd >= 0.0 && sd.is_finite()) {
+                    return Err(format!("sd: {} is not a standard deviation", sd));
+                }
+                if mean < min || mean > max {
+                    return Err(format!("mean: {} is not between min and max", mean));
+                }
+            }
+            SpeedDistribution::Fixed { speed: fixed } => return speed("speed", fixed),
+        }
+        let (min, max) = self.range();
+        if min > max {
+            return Err(format!("max: {} is below min {}", max, min));
+        }
+        Ok(())
+    }
+
+    /*
+    Returns the lowest and highest speed the distribution can draw.
+    */
+    pub fn range(&self) -> (f64, f64) {
+        match *self {
+            SpeedDistribution::Uniform { min, max }
+            | SpeedDistribution::Normal { min, max, .. } => (min, max),
+            SpeedDistribution::Fixed { speed } => (speed, speed),
+        }
+    }
+
+    /*
+    Draws one desired speed. A normal draw outside min and max is drawn again, up to a hundred times before it is clamped, so a narrow range far in a tail cannot stall the run.
+    */
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> f64 {
+        match *self {
+            SpeedDistribution::Uniform { min, max } => rng.random_range(min..=max),
+            SpeedDistribution::Normal { mean, sd, min, max } => {
+                let mut speed = mean;
+                for _ in 0..100 {
+                    let radius = (-2.0 * (1.0 - rng.random::<f64>()).ln()).sqrt();
+                    let angle = std::f64::consts::TAU * rng.random::<f64>();
+                    speed = mean + sd * radius * angle.cos();
+                    if (min..=max).contains(&speed) {
+                        return speed;
+                    }
+                }
+                speed.clamp(min, max)
+            }
+            SpeedDistribution::Fixed { speed } => speed,
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+
+use super::car::*;
+use super::conflict::*;
+use super::constants::*;
+use super::controller::*;
+use super::road::*;
+use super::se_base::*;
+use super::vehicle::*;
+
+/*
+The TripRecord struct is what the StatsCollector knows about one vehicle. All times are simulation ticks:
+
+spawned: when the vehicle arrived, which is before it was put on the road when it had to wait for room at the start of its lane
+arrived: when its front first reached the stop line
+entered: when it entered the intersection
+exited: when it left the intersection
+stops: how many times it came to a standstill (its speed dropping below STOPPED_SPEED) before leaving the intersection
+delay: the time lost against driving at its desired speed, slowed down for its turn as in Car::speed_limit, summed over every tick until it left the intersection; every tick spent waiting for room at the start of its lane counts in full
+stopped: whether the vehicle was standing still on the last recorded tick
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct TripRecord {
+    pub id: u64,
+    pub side: Side,
+    pub direction: Direction,
+    pub class: VehicleClass,
+    pub spawned: u64,
+    pub arrived: Option<u64>,
+    pub entered: Option<u64>,
+    pub exited: Option<u64>,
+    pub stops: u32,
+    pub delay: f64,
+    pub stopped: bool,
+}
+
+impl TripRecord {
+    pub fn new(car: &Car, spawned: u64) -> TripRecord {
+        TripRecord {
+            id: car.id,
+            side: car.side,
+            direction: car.direction,
+            class: car.class,
+            spawned,
+            arrived: None,
+            entered: None,
+            exited: None,
+            stops: 0,
+            delay: 0.0,
+            stopped: false,
+        }
+    }
+
+    pub fn movement(&self) -> Movement {
+        Movement::new(self.side, self.direction)
+    }
+}
+
+/*
+One point of the per-approach time series, taken every STATS_SAMPLE_TICKS ticks. Both arrays are indexed by Side::index, then Direction::index:
+
+queues: the number of cars of each movement in the cars_before_stop_* queues at the sample tick
+throughput: the number of cars of each movement that left the intersection since the previous sample
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueSample {
+    pub tick: u64,
+    pub queues: [[usize; 3]; 4],
+    pub throughput: [[usize; 3]; 4],
+}
+
+/*
+The state of the four lights at the end of a tick.
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LightSample {
+    pub tick: u64,
+    pub lights: SignalState,
+}
+
+/*
+The StatsCollector is fed by Road::simulation_loop on every tick. It keeps a TripRecord per vehicle id, the ids of the vehicles that left the intersection in finished, in the order they left, the per-approach QueueSample time series and a LightSample per tick, and counts in exits the cars that left the intersection since the last sample.
+*/
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StatsCollector {
+    pub trips: BTreeMap<u64, TripRecord>,
+    pub finished: Vec<u64>,
+    pub samples: Vec<QueueSample>,
+    pub lights: Vec<LightSample>,
+    pub exits: [[usize; 3]; 4],
+}
+
+impl StatsCollector {
+    /*
+    Opens the trip record of a vehicle put on the road at the given tick.
+    */
+    pub fn spawned(&mut self, car: &Car, now: u64) {
+        self.trips.insert(car.id, TripRecord::new(car, now));
+    }
+
+    /*
+    Records the state of the road after a tick: the delay of the vehicles still waiting in entering, the delay and stops of every vehicle on its way through the intersection, stop line arrivals, entries and exits, the lights, and a QueueSample every STATS_SAMPLE_TICKS ticks.
+    A vehicle that was not spawned through Road::spawn gets its record on the first tick it is seen.
+    */
+    pub fn record(&mut self, road: &Road) {
+        let now = road.tick;
+        for car in &road.entering {
+            self.trips
+                .entry(car.id)
+                .or_insert_with(|| TripRecord::new(car, now))
+                .delay += 1.0;
+        }
+        for side in Side::ALL {
+            for car in road.cars_before_stop(side) {
+                let trip = self.update(car, now);
+                if trip.arrived.is_none() && car.at_stop_line() {
+                    trip.arrived = Some(now);
+                }
+            }
+        }
+        for car in &road.cars_in_intersection {
+            let trip = self.update(car, now);
+            trip.arrived.get_or_insert(now);
+            trip.entered.get_or_insert(now);
+        }
+        for leg in Side::ALL {
+            for car in road.cars_after_stop(leg).iter().rev() {
+                if self
+                    .trips
+                    .get(&car.id)
+                    .is_some_and(|trip| trip.exited.is_some())
+                {
+                    break;
+                }
+                let trip = self.update(car, now);
+                trip.arrived.get_or_insert(now);
+                trip.entered.get_or_insert(now);
+                trip.exited = Some(now);
+                self.finished.push(car.id);
+                self.exits[car.side.index()][car.direction.index()] += 1;
+            }
+        }
+        self.lights.push(LightSample {
+            tick: now,
+            lights: SignalState {
+                north: road.north_lights.clone(),
+                east: road.east_lights.clone(),
+                south: road.south_lights.clone(),
+                west: road.west_lights.clone(),
+            },
+        });
+        if now.is_multiple_of(STATS_SAMPLE_TICKS) {
+            let mut queues = [[0; 3]; 4];
+            for side in Side::ALL {
+                for car in road.cars_before_stop(side) {
+                    queues[side.index()][car.direction.index()] += 1;
+                }
+            }
+            self.samples.push(QueueSample {
+                tick: now,
+                queues,
+                throughput: std::mem::take(&mut self.exits),
+            });
+        }
+    }
+
+    /*
+    Computes the aggregates of the run so far, with the given pedestrian waiting times reported alongside.
+    */
+    pub fn stats(&self, pedestrian_waits: impl IntoIterator<Item = f64>) -> Stats {
+        let trips = &self.trips;
+        let done = || trips.values().filter(|trip| trip.exited.is_some());
+        let mut movements = vec![];
+        for side in Side::ALL {
+            for direction in Direction::ALL {
+                let movement = Movement::new(side, direction);
+                let samples = &self.samples;
+                movements.push(MovementStats {
+                    movement,
+                    delay: Aggregate::of(
+                        done()
+                            .filter(|trip| trip.movement() == movement)
+                            .map(|trip| trip.delay),
+                    ),
+                    queue: Aggregate::of(
+                        samples
+                            .iter()
+                            .map(|sample| sample.queues[side.index()][direction.index()] as f64),
+                    ),
+                    throughput: done().filter(|trip| trip.movement() == movement).count(),
+                });
+            }
+        }
+        Stats {
+            spawned: trips.len(),
+            delay: Aggregate::of(done().map(|trip| trip.delay)),
+            stops: Aggregate::of(done().map(|trip| trip.stops as f64)),
+            travel_time: Aggregate::of(
+                done().filter_map(|trip| trip.exited.map(|exit| (exit - trip.spawned) as f64)),
+            ),
+            pedestrian_wait: Aggregate::of(pedestrian_waits),
+            movements,
+        }
+    }
+
+    /*
+    Adds one tick of delay to the vehicle's record and counts a stop when it has just come to a standstill.
+    */
+    fn update(&mut self, car: &Car, now: u64) -> &mut TripRecord {
+        let trip = self
+            .trips
+            .entry(car.id)
+            .or_insert_with(|| TripRecord::new(car, now));
+        let free = car.speed_limit();
+        if free > 0.0 {
+            trip.delay += (1.0 - car.speed / free).max(0.0);
+        }
+        let stopped = car.speed < STOPPED_SPEED;
+        if stopped && !trip.stopped {
+            trip.stops += 1;
+        }
+        trip.stopped = stopped;
+        trip
+    }
+}
+
+/*
+Summary of a set of values: how many there are, their mean, 95th percentile (nearest rank) and maximum. All are 0 for an empty set.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Aggregate {
+    pub count: usize,
+    pub mean: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+impl Aggregate {
+    pub fn of(values: impl IntoIterator<Item = f64>) -> Aggregate {
+        let mut values: Vec<f64> = values.into_iter().collect();
+        if values.is_empty() {
+            return Aggregate::default();
+        }
+        values.sort_by(f64::total_cmp);
+        let count = values.len();
+        let rank = (count as f64 * 0.95).ceil() as usize;
+        Aggregate {
+            count,
+            mean: values.iter().sum::<f64>() / count as f64,
+            p95: values[rank.clamp(1, count) - 1],
+            max: values[count - 1],
+        }
+    }
+}
+
+/*
+The figures of one movement, a Side and Direction pair:
+
+delay: the delay in ticks of its vehicles that left the intersection
+queue: its queue length over the samples
+throughput: the number of its vehicles that left the intersection
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovementStats {
+    pub movement: Movement,
+    pub delay: Aggregate,
+    pub queue: Aggregate,
+    pub throughput: usize,
+}
+
+/*
+The aggregates returned by Road::stats. Times are in ticks, and only vehicles that left the intersection count towards delay, stops and travel times:
+
+spawned: the number of vehicles put on the road
+delay: the delay per vehicle
+stops: the number of stops per vehicle
+travel_time: the time from spawning to leaving the intersection
+pedestrian_wait: the time pedestrians waited at the curb, counting those still waiting up to now
+movements: the figures of the twelve movements, in Side::ALL then Direction::ALL order
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub spawned: usize,
+    pub delay: Aggregate,
+    pub stops: Aggregate,
+    pub travel_time: Aggregate,
+    pub pedestrian_wait: Aggregate,
+    pub movements: Vec<MovementStats>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_take_the_nearest_rank() {
+        assert_eq!(Aggregate::of([]), Aggregate::default());
+        let one = Aggregate::of([4.0]);
+        assert_eq!((one.count, one.mean, one.p95, one.max), (1, 4.0, 4.0, 4.0));
+        let twenty = Aggregate::of((1..=20).rev().map(f64::from));
+        assert_eq!(twenty.count, 20);
+        assert_eq!(twenty.mean, 10.5);
+        assert_eq!(twenty.p95, 19.0);
+        assert_eq!(twenty.max, 20.0);
+    }
+
+    #[test]
+    fn trips_are_followed_through_the_intersection() {
+        let mut road = Road::new();
+        let car =
+            |side| Car::with_speed(side, Direction::Straight, VehicleClass::PassengerCar, 150.0);
+        road.spawn(car(Side::FromNorth));
+        road.spawn(car(Side::FromEast));
+        while road.collector.finished.len() < 2 {
+            assert!(road.tick < 120 * TICKS_PER_SECOND as u64);
+            road.simulation_loop();
+        }
+        let collector = &road.collector;
+        for trip in collector.trips.values() {
+            let (arrived, entered, exited) = (
+                trip.arrived.unwrap(),
+                trip.entered.unwrap(),
+                trip.exited.unwrap(),
+            );
+            assert!(trip.spawned <= arrived && arrived <= entered && entered < exited);
+        }
+        let [first, second] = [0, 1].map(|i| &collector.trips[&collector.finished[i]]);
+        assert_eq!(first.stops, 0);
+        assert!(second.stops >= 1);
+        assert!(second.delay > first.delay);
+
+        let stats = road.stats();
+        assert_eq!(stats.spawned, 2);
+        assert_eq!(stats.delay.count, 2);
+        assert_eq!(stats.delay.max, second.delay);
+        let served: usize = stats
+            .movements
+            .iter()
+            .map(|movement| movement.throughput)
+            .sum();
+        assert_eq!(served, 2);
+        let north = Movement::new(Side::FromNorth, Direction::Straight);
+        let figures = stats
+            .movements
+            .iter()
+            .find(|m| m.movement == north)
+            .unwrap();
+        assert_eq!(figures.throughput, 1);
+
+        for _ in 0..STATS_SAMPLE_TICKS {
+            road.simulation_loop();
+        }
+        let sampled: usize = road
+            .collector
+            .samples
+            .iter()
+            .flat_map(|sample| sample.throughput.iter().flatten())
+            .sum();
+        assert_eq!(sampled, 2);
+        assert_eq!(road.collector.lights.len() as u64, road.tick);
+    }
+}
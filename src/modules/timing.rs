@@ -0,0 +1,236 @@
+use super::constants::*;
+use super::controller::*;
+use super::se_base::*;
+
+/*
+The SignalTiming struct holds the durations, in ticks, that shape a signal phase:
+
+min_green: the shortest green a phase may get before the controller is allowed to end it
+green: the longest green of each phase, indexed by Side::index
+amber: how long the amber interval lasts after a green
+all_red: the clearance interval during which every approach is red before the next phase starts
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignalTiming {
+    pub min_green: u32,
+    pub green: [u32; 4],
+    pub amber: u32,
+    pub all_red: u32,
+}
+
+impl Default for SignalTiming {
+    fn default() -> SignalTiming {
+        SignalTiming {
+            min_green: MIN_GREEN_TICKS,
+            green: [GREEN_TICKS; 4],
+            amber: AMBER_TICKS,
+            all_red: ALL_RED_TICKS,
+        }
+    }
+}
+
+/*
+The interval a phase is in. Idle means the clearance has finished and no phase has been started yet.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interval {
+    Green,
+    Amber,
+    AllRed,
+    Idle,
+}
+
+/*
+The PhaseTimer is the state machine shared by the timed controllers. It serves one approach at a time and walks it through green, amber and all-red clearance:
+
+phase: the approach currently being served, if any
+interval: where the phase is in its green, amber, all-red sequence
+elapsed: the ticks spent in the current interval
+duration: the length of the current interval, for green this is the phase's maximum green
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseTimer {
+    pub timing: SignalTiming,
+    pub phase: Option<Side>,
+    pub interval: Interval,
+    pub elapsed: u32,
+    pub duration: u32,
+}
+
+impl Default for PhaseTimer {
+    fn default() -> PhaseTimer {
+        PhaseTimer::new(SignalTiming::default())
+    }
+}
+
+impl PhaseTimer {
+    pub fn new(timing: SignalTiming) -> PhaseTimer {
+        PhaseTimer {
+            timing,
+            phase: None,
+            interval: Interval::Idle,
+            elapsed: 0,
+            duration: 0,
+        }
+    }
+
+    /*
+    Advances the timer by one tick. A green that reaches its maximum turns amber, an amber turns all red and a finished all-red clearance leaves the timer idle, waiting for the controller to start the next phase.
+    */
+    pub fn advance(&mut self) {
+        if self.interval == Interval::Idle {
+            return;
+        }
+        self.elapsed += 1;
+        if self.elapsed >= self.duration {
+            self.next_interval();
+        }
+    }
+
+    /*
+    Starts a green for the given approach, lasting at most its per-phase green duration. Only takes effect when the timer is idle.
+    */
+    pub fn start(&mut self, side: Side) {
+        if self.interval != Interval::Idle {
+            return;
+        }
+        self.phase = Some(side);
+        let green = self.timing.green[side.index()].max(self.timing.min_green);
+        self.enter(Interval::Green, green);
+    }
+
+    /*
+    Ends the current green early and moves on to amber. The request is ignored until the minimum green has been served.
+    */
+    pub fn end_green(&mut self) {
+        if self.can_end_green() {
+            self.enter(Interval::Amber, self.timing.amber);
+        }
+    }
+
+    pub fn can_end_green(&self) -> bool {
+        self.interval == Interval::Green && self.elapsed >= self.timing.min_green
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.interval == Interval::Idle
+    }
+
+    pub fn remaining(&self) -> u32 {
+        self.duration.saturating_sub(self.elapsed)
+    }
+
+    /*
+    Returns the light shown on every approach. The served approach shows green or amber with the ticks left in that interval, every other approach is red.
+    */
+    pub fn state(&self) -> SignalState {
+        let mut state = SignalState::all_red();
+        if let Some(side) = self.phase {
+            let color = match self.interval {
+                Interval::Green => Light::Green,
+                Interval::Amber => Light::Amber,
+                Interval::AllRed | Interval::Idle => Light::Red,
+            };
+            *state.light_mut(side) = TrafficLight {
+                color,
+                remaining: self.remaining(),
+            };
+        }
+        state
+    }
+
+    fn next_interval(&mut self) {
+        match self.interval {
+            Interval::Green => self.enter(Interval::Amber, self.timing.amber),
+            Interval::Amber => self.enter(Interval::AllRed, self.timing.all_red),
+            Interval::AllRed | Interval::Idle => {
+                self.phase = None;
+                self.interval = Interval::Idle;
+                self.elapsed = 0;
+                self.duration = 0;
+            }
+        }
+    }
+
+    fn enter(&mut self, interval: Interval, duration: u32) {
+        self.interval = interval;
+        self.elapsed = 0;
+        self.duration = duration;
+        if duration == 0 {
+            self.next_interval();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing() -> SignalTiming {
+        SignalTiming {
+            min_green: 3,
+            green: [5, 6, 7, 8],
+            amber: 2,
+            all_red: 1,
+        }
+    }
+
+    /*
+    Advances the timer until it is idle again and returns the intervals it went through, one per tick.
+    */
+    fn run(timer: &mut PhaseTimer) -> Vec<Interval> {
+        let mut intervals = vec![timer.interval];
+        while !timer.is_idle() {
+            timer.advance();
+            intervals.push(timer.interval);
+        }
+        intervals
+    }
+
+    #[test]
+    fn a_phase_runs_green_amber_and_all_red() {
+        let mut timer = PhaseTimer::new(timing());
+        timer.start(Side::FromEast);
+        assert_eq!(timer.state().east.color, Light::Green);
+        assert_eq!(timer.state().east.remaining, 6);
+        assert_eq!(timer.state().north, TrafficLight::new(Light::Red));
+        let intervals = run(&mut timer);
+        let count = |interval| intervals.iter().filter(|i| **i == interval).count();
+        assert_eq!(count(Interval::Green), 6);
+        assert_eq!(count(Interval::Amber), 2);
+        assert_eq!(count(Interval::AllRed), 1);
+        assert_eq!(timer.phase, None);
+        assert_eq!(timer.state(), SignalState::all_red());
+    }
+
+    #[test]
+    fn a_green_ends_early_only_after_the_minimum() {
+        let mut timer = PhaseTimer::new(timing());
+        timer.start(Side::FromWest);
+        timer.advance();
+        timer.end_green();
+        assert_eq!(timer.interval, Interval::Green);
+        timer.start(Side::FromNorth);
+        assert_eq!(timer.phase, Some(Side::FromWest));
+        timer.advance();
+        timer.advance();
+        timer.end_green();
+        assert_eq!(timer.interval, Interval::Amber);
+        assert_eq!(timer.state().west.color, Light::Amber);
+        assert_eq!(timer.state().west.remaining, 2);
+    }
+
+    #[test]
+    fn intervals_of_no_length_are_skipped() {
+        let mut timer = PhaseTimer::new(SignalTiming {
+            amber: 0,
+            all_red: 0,
+            ..timing()
+        });
+        timer.start(Side::FromSouth);
+        let intervals = run(&mut timer);
+        assert_eq!(intervals.len(), 8);
+        assert!(intervals[..7].iter().all(|i| *i == Interval::Green));
+        assert_eq!(intervals[7], Interval::Idle);
+    }
+}
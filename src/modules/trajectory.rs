@@ -0,0 +1,378 @@
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use super::conflict::*;
+use super::constants::*;
+use super::geometry::*;
+use super::se_base::*;
+use super::vehicle::*;
+
+/*
+A point on the canvas, in pixels with sub-pixel precision.
+*/
+pub type Point = (f64, f64);
+
+/*
+The Trajectory struct is the path the middle of a car's front bumper follows through its movement: a straight approach along the middle of its lane up to its stop line, a quarter circle from the stop line to the far edge of the crosswalk of its exit leg for a turn, and a straight exit along the middle of the lane of the same number on the exit leg. Positions on the path are distances in pixels from where the car is spawned:
+
+start: where the front bumper is when the car is spawned, one lane width in from the end of the road
+heading: the angle of the approach, in radians on the canvas, 0 towards the right and PI / 2 downwards
+stop_line: the distance from start to the stop line
+hold: how far short of the stop line the car waits, 0 unless Road sets it from ConflictMatrix::hold while a long vehicle turns over the approach
+radius: the radius of the turn, 0 for a straight movement
+turn: 1 for a right turn, -1 for a left turn and 0 for a straight movement, the way the heading changes along the arc
+lane: the lane the movement drives in, numbered from the center line as in Geometry
+lane_width: the width of the lanes
+clear: the distance from start at which the front bumper leaves the intersection box
+end: the distance from start at which the front bumper reaches the end of the exit road
+
+A right turn stays on the near side of the intersection and a left turn crosses it, so the right turn is the tighter of the two.
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trajectory {
+    pub start: Point,
+    pub heading: f64,
+    pub stop_line: f64,
+    pub hold: f64,
+    pub radius: f64,
+    pub turn: f64,
+    pub lane: i32,
+    pub lane_width: f64,
+    pub clear: f64,
+    pub end: f64,
+}
+
+impl Trajectory {
+    /*
+    Lays out the path of a movement through the intersection described by the geometry. The radius of a turn is what takes the car from the middle of its lane at the stop line to the middle of its exit lane at the far edge of the exit crosswalk.
+    */
+    pub fn new(geometry: &Geometry, movement: Movement) -> Trajectory {
+        let heading = side_heading(movement.side);
+        let (dx, dy) = (heading.cos().round(), heading.sin().round());
+        let (center_x, center_y) = geometry.center();
+        let lane_width = geometry.lane_width as f64;
+        let lane = geometry.lane(movement.direction);
+        let offset = (lane as f64 + 0.5) * lane_width;
+        let to_stop_line = geometry.stop_line() as f64;
+        let half_box = geometry.half_box() as f64;
+        let back = geometry.reach() as f64 - lane_width;
+        let turn = match movement.direction {
+            Direction::Right => 1.0,
+            Direction::Left => -1.0,
+            Direction::Straight => 0.0,
+        };
+        let stop_line = back - to_stop_line;
+        let (radius, clear) = if turn == 0.0 {
+            (0.0, stop_line + (to_stop_line + half_box))
+        } else {
+            let radius = to_stop_line - turn * offset;
+            (
+                radius,
+                stop_line + radius * FRAC_PI_2 + (half_box - radius - turn * offset),
+            )
+        };
+        Trajectory {
+            start: (
+                center_x as f64 - dx * back - dy * offset,
+                center_y as f64 - dy * back + dx * offset,
+            ),
+            heading,
+            stop_line,
+            hold: 0.0,
+            radius,
+            turn,
+            lane,
+            lane_width,
+            clear,
+            end: clear + (geometry.reach() - geometry.half_box()) as f64,
+        }
+    }
+
+    /*
+    Returns the unit vector across the approach that points from the center line towards the curb.
+    */
+    pub fn curb(&self) -> Point {
+        (-self.heading.sin(), self.heading.cos())
+    }
+
+    /*
+    Returns the length of the path from start to the end of the turn, which is the stop line for a straight movement.
+    */
+    pub fn turn_end(&self) -> f64 {
+        self.stop_line + self.radius * FRAC_PI_2
+    }
+
+    /*
+    Returns the highest speed in pixels per second a car may drive the turn at, keeping its lateral acceleration within MAX_LATERAL_ACCELERATION, or None for a straight movement.
+    */
+    pub fn turn_speed(&self) -> Option<f64> {
+        if self.turn == 0.0 {
+            return None;
+        }
+        Some((MAX_LATERAL_ACCELERATION * self.radius).sqrt())
+    }
+
+    /*
+    Returns the point at the given distance along the path and the heading there. Distances before start carry on the approach backwards and distances past the turn carry on the exit.
+    */
+    pub fn point(&self, distance: f64) -> (Point, f64) {
+        let along = |(x, y): Point, heading: f64, distance: f64| {
+            (x + distance * heading.cos(), y + distance * heading.sin())
+        };
+        let entry = along(self.start, self.heading, distance.min(self.stop_line));
+        if distance <= self.stop_line || self.turn == 0.0 {
+            return (along(self.start, self.heading, distance), self.heading);
+        }
+        let (normal_x, normal_y) = (-self.heading.sin(), self.heading.cos());
+        let center = (
+            entry.0 + self.turn * self.radius * normal_x,
+            entry.1 + self.turn * self.radius * normal_y,
+        );
+        let angle = ((distance - self.stop_line) / self.radius).min(FRAC_PI_2);
+        let heading = self.heading + self.turn * angle;
+        let arc = (
+            center.0 + self.turn * self.radius * heading.sin(),
+            center.1 - self.turn * self.radius * heading.cos(),
+        );
+        (
+            along(arc, heading, (distance - self.turn_end()).max(0.0)),
+            heading,
+        )
+    }
+
+    /*
+    Returns the four corners of a vehicle of the given length and width whose front bumper is at the given distance along the path. The rear of the vehicle follows the path too, so the body lies along the chord between its front and rear bumpers and cuts the inside of a turn, as a real vehicle does.
+    */
+    pub fn body(&self, distance: f64, length: f64, width: f64) -> [Point; 4] {
+        let (front, heading) = self.point(distance);
+        let (rear, _) = self.point(distance - length);
+        let (dx, dy) = (front.0 - rear.0, front.1 - rear.1);
+        let chord = (dx * dx + dy * dy).sqrt();
+        let (ux, uy) = if chord > 0.0 {
+            (dx / chord, dy / chord)
+        } else {
+            (heading.cos(), heading.sin())
+        };
+        let (nx, ny) = (-uy * width / 2.0, ux * width / 2.0);
+        let back = (front.0 - ux * length, front.1 - uy * length);
+        [
+            (front.0 + nx, front.1 + ny),
+            (front.0 - nx, front.1 - ny),
+            (back.0 - nx, back.1 - ny),
+            (back.0 + nx, back.1 + ny),
+        ]
+    }
+
+    /*
+    Returns the distance along the path at which a vehicle of the given length has left the intersection box with its rear bumper, by the leg its movement exits by.
+    */
+    pub fn exit(&self, length: f64) -> f64 {
+        self.clear + length
+    }
+
+    /*
+    Returns the distance along the path at which a vehicle of the given length has left the intersection box and, after a turn, has its rear bumper out of the turn as well, so that from there on its body lies straight in its exit lane.
+    */
+    pub fn settled(&self, length: f64) -> f64 {
+        self.exit(length).max(self.turn_end() + length)
+    }
+
+    /*
+    Returns the bodies of a vehicle of the given length and width every SWEEP_STEP along the path, from one distance to another, which together cover the area it sweeps.
+    */
+    pub fn sweep(&self, from: f64, to: f64, length: f64, width: f64) -> Vec<[Point; 4]> {
+        let mut bodies = vec![];
+        let mut distance = from;
+        while distance <= to {
+            bodies.push(self.body(distance, length, width));
+            distance += SWEEP_STEP;
+        }
+        bodies
+    }
+}
+
+/*
+Returns the heading of a car driving in along the approach of the given side.
+*/
+pub fn side_heading(side: Side) -> f64 {
+    match side {
+        Side::FromEast => 0.0,
+        Side::FromNorth => FRAC_PI_2,
+        Side::FromWest => PI,
+        Side::FromSouth => -FRAC_PI_2,
+    }
+}
+
+/*
+Returns the corners of the rectangle (x, y, width, height).
+*/
+pub fn rectangle(x: f64, y: f64, width: f64, height: f64) -> [Point; 4] {
+    [
+        (x, y),
+        (x + width, y),
+        (x + width, y + height),
+        (x, y + height),
+    ]
+}
+
+/*
+Returns the bounding box of a polygon, as its left, top, right and bottom edges.
+*/
+pub fn bounds(polygon: &[Point]) -> (f64, f64, f64, f64) {
+    polygon.iter().fold(
+        (
+            f64::INFINITY,
+            f64::INFINITY,
+            f64::NEG_INFINITY,
+            f64::NEG_INFINITY,
+        ),
+        |(left, top, right, bottom), &(x, y)| {
+            (left.min(x), top.min(y), right.max(x), bottom.max(y))
+        },
+    )
+}
+
+/*
+Returns whether any body of a sweep overlaps the given polygon. Bodies whose bounding boxes lie apart from it are not compared further.
+*/
+pub fn sweeps_over(sweep: &[[Point; 4]], polygon: &[Point]) -> bool {
+    let b = bounds(polygon);
+    sweep.iter().any(|body| {
+        let a = bounds(body);
+        a.0 < b.2 && b.0 < a.2 && a.1 < b.3 && b.1 < a.3 && polygons_overlap(body, polygon)
+    })
+}
+
+/*
+Returns whether two convex polygons overlap, by the separating axis test. Polygons that only touch along an edge do not overlap, so vehicles in neighbouring lanes are kept apart.
+*/
+pub fn polygons_overlap(a: &[Point], b: &[Point]) -> bool {
+    let separated = |polygon: &[Point]| {
+        (0..polygon.len()).any(|i| {
+            let (x1, y1) = polygon[i];
+            let (x2, y2) = polygon[(i + 1) % polygon.len()];
+            let axis = (y1 - y2, x2 - x1);
+            let project = |points: &[Point]| {
+                points
+                    .iter()
+                    .map(|(x, y)| x * axis.0 + y * axis.1)
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), p| {
+                        (min.min(p), max.max(p))
+                    })
+            };
+            let (min_a, max_a) = project(a);
+            let (min_b, max_b) = project(b);
+            let length = (axis.0 * axis.0 + axis.1 * axis.1).sqrt();
+            max_a.min(max_b) - min_a.max(min_b) <= OVERLAP_TOLERANCE * length
+        })
+    };
+    !separated(a) && !separated(b)
+}
+
+/*
+Sweeps the longest vehicle class along the path of every movement through the given intersection, indexed by Movement::index, from its stop line until it has left the box with its rear out of its turn. A long vehicle cuts the inside of its turn and swings its rear over the edge of its lane on the way out, so its sweep reaches the crosswalks and the approaches next to its own lanes, and whatever a shorter vehicle sweeps lies within it.
+*/
+pub fn design_sweeps(geometry: &Geometry) -> Vec<Vec<[Point; 4]>> {
+    class_sweeps(geometry, VehicleClass::longest())
+}
+
+/*
+Sweeps a vehicle of the given class along the path of every movement, indexed by Movement::index, the way design_sweeps does for the longest class.
+*/
+pub fn class_sweeps(geometry: &Geometry, class: VehicleClass) -> Vec<Vec<[Point; 4]>> {
+    let spec = class.spec();
+    let (length, width) = (spec.length as f64, spec.width as f64);
+    Movement::ALL
+        .iter()
+        .map(|&movement| {
+            let trajectory = Trajectory::new(geometry, movement);
+            trajectory.sweep(
+                trajectory.stop_line,
+                trajectory.settled(length),
+                length,
+                width,
+            )
+        })
+        .collect()
+}
+
+/*
+Returns, indexed by Side::index, how far short of its stop line the front of a vehicle of the longest class pulled over to the curb, a lane width out of the curb lane of the approach, has to stand to be clear of all the given design_sweeps. It is a multiple of SWEEP_STEP.
+*/
+pub fn swept_asides(geometry: &Geometry, sweeps: &[Vec<[Point; 4]>]) -> [f64; 4] {
+    let spec = VehicleClass::longest().spec();
+    let (length, width) = (spec.length as f64, spec.width as f64);
+    Side::ALL.map(|side| {
+        let trajectory = Trajectory::new(geometry, Movement::new(side, Direction::Right));
+        let (dx, dy) = trajectory.curb();
+        let shift = trajectory.lane_width;
+        let mut aside = 0.0;
+        while aside < trajectory.stop_line {
+            let body = trajectory
+                .body(trajectory.stop_line - aside, length, width)
+                .map(|(x, y)| (x + dx * shift, y + dy * shift));
+            if !sweeps.iter().any(|sweep| sweeps_over(sweep, &body)) {
+                break;
+            }
+            aside += SWEEP_STEP;
+        }
+        aside
+    })
+}
+
+/*
+Returns whether each pair of movements from different sides covers a common area of the given design_sweeps, indexed by Movement::index. Bodies whose bounding boxes lie apart are not compared further.
+*/
+pub fn swept_conflicts(sweeps: &[Vec<[Point; 4]>]) -> [[bool; 12]; 12] {
+    let extents: Vec<Vec<(f64, f64, f64, f64)>> = sweeps
+        .iter()
+        .map(|bodies| bodies.iter().map(|body| bounds(body)).collect())
+        .collect();
+    let mut conflicts = [[false; 12]; 12];
+    for (i, movement) in Movement::ALL.iter().enumerate() {
+        for (j, other) in Movement::ALL.iter().enumerate() {
+            if movement.side == other.side || j < i {
+                continue;
+            }
+            let overlap = sweeps[i].iter().zip(&extents[i]).any(|(body, a)| {
+                sweeps[j].iter().zip(&extents[j]).any(|(other, b)| {
+                    a.0 < b.2
+                        && b.0 < a.2
+                        && a.1 < b.3
+                        && b.1 < a.3
+                        && polygons_overlap(body, other)
+                })
+            });
+            conflicts[movement.index()][other.index()] = overlap;
+            conflicts[other.index()][movement.index()] = overlap;
+        }
+    }
+    conflicts
+}
+
+/*
+Returns, indexed by the Movement::index of a vehicle driving through and then by that of a vehicle waiting at its stop line, how far short of the line the waiting vehicle, standing there as long as the longest class, has to be to stay clear of the given sweeps of the one driving through, from class_sweeps. It is 0 for movements from the same side and where the sweep does not reach the approach, and a multiple of SWEEP_STEP otherwise.
+*/
+pub fn swept_holds(geometry: &Geometry, sweeps: &[Vec<[Point; 4]>]) -> [[f64; 12]; 12] {
+    let spec = VehicleClass::longest().spec();
+    let (length, width) = (spec.length as f64, spec.width as f64);
+    let mut holds = [[0.0; 12]; 12];
+    for waiting in Movement::ALL {
+        let trajectory = Trajectory::new(geometry, waiting);
+        for driving in Movement::ALL {
+            if driving.side == waiting.side {
+                continue;
+            }
+            let sweep = &sweeps[driving.index()];
+            let mut hold = 0.0;
+            while hold < trajectory.stop_line {
+                let body = trajectory.body(trajectory.stop_line - hold, length, width);
+                if !sweeps_over(sweep, &body) {
+                    break;
+                }
+                hold += SWEEP_STEP;
+            }
+            holds[driving.index()][waiting.index()] = hold;
+        }
+    }
+    holds
+}
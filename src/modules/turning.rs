@@ -0,0 +1,164 @@
+use rand::Rng;
+
+use super::conflict::*;
+use super::se_base::*;
+
+/*
+The TurningProportions struct sets how the vehicles of each approach split over the three movements. shares holds one non-negative weight per Direction for every Side, indexed by Side::index then Direction::index; the weights of an approach do not need to add up to one.
+The default sends a third of every approach each way.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurningProportions {
+    pub shares: [[f64; 3]; 4],
+}
+
+impl Default for TurningProportions {
+    fn default() -> TurningProportions {
+        TurningProportions {
+            shares: [[1.0; 3]; 4],
+        }
+    }
+}
+
+impl TurningProportions {
+    /*
+    Sets the left, straight and right weights of one approach, for example [10.0, 75.0, 15.0] for percentages.
+    */
+    pub fn with_side(mut self, side: Side, shares: [f64; 3]) -> TurningProportions {
+        self.shares[side.index()] = shares;
+        self
+    }
+
+    /*
+    Builds the proportions from an origin-destination matrix over the four legs. matrix[origin][destination] is the number of vehicles, or share, that come from the origin Side and leave by the destination leg, both indexed by Side::index, a leg being named after the Side whose vehicles enter on it.
+    Every movement leaves by a different leg, so each off-diagonal cell is the weight of one movement. The diagonal would be a U-turn, which the intersection does not allow; it is ignored here and rejected by validate_od.
+    */
+    pub fn from_od(matrix: [[f64; 4]; 4]) -> TurningProportions {
+        let mut shares = [[0.0; 3]; 4];
+        for side in Side::ALL {
+            for direction in Direction::ALL {
+                let leg = Movement::new(side, direction).exit_leg();
+                shares[side.index()][direction.index()] = matrix[side.index()][leg.index()];
+            }
+        }
+        TurningProportions { shares }
+    }
+
+    /*
+    Checks an origin-destination matrix for from_od: no weight may be negative or infinite, and the diagonal must be zero.
+    */
+    pub fn validate_od(matrix: &[[f64; 4]; 4]) -> Result<(), String> {
+        for (origin, row) in matrix.iter().enumerate() {
+            for (destination, weight) in row.iter().enumerate() {
+                if !(*weight >= 0.0 && weight.is_finite()) {
+                    return Err(format!(
+                        "od[{}][{}]: {} is not a share",
+                        origin, destination, weight
+                    ));
+                }
+                if origin == destination && *weight > 0.0 {
+                    return Err(format!(
+                        "od[{}][{}]: vehicles cannot leave by the leg they came from",
+                        origin, destination
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    Checks that no weight is negative or infinite and that every approach has a positive weight.
+    */
+    pub fn validate(&self) -> Result<(), String> {
+        for side in Side::ALL {
+            let shares = &self.shares[side.index()];
+            for direction in Direction::ALL {
+                let share = shares[direction.index()];
+                if !(share >= 0.0 && share.is_finite()) {
+                    return Err(format!(
+                        "shares[{}][{}]: {} is not a share",
+                        side.index(),
+                        direction.index(),
+                        share
+                    ));
+                }
+            }
+            if shares.iter().sum::<f64>() <= 0.0 {
+                return Err(format!(
+                    "shares[{}]: the approach needs at least one positive share",
+                    side.index()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /*
+    Returns the share of the given movement among the vehicles of its approach, between 0 and 1.
+    */
+    pub fn proportion(&self, side: Side, direction: Direction) -> f64 {
+        let shares = &self.shares[side.index()];
+        let total: f64 = shares.iter().map(|share| share.max(0.0)).sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        shares[direction.index()].max(0.0) / total
+    }
+
+    /*
+    Draws the direction of a vehicle coming from the given side with probability proportional to its share. An approach without any positive share goes straight.
+    */
+    pub fn sample<R: Rng + ?Sized>(&self, side: Side, rng: &mut R) -> Direction {
+        let shares = &self.shares[side.index()];
+        let total: f64 = shares.iter().map(|share| share.max(0.0)).sum();
+        if total <= 0.0 {
+            return Direction::Straight;
+        }
+        let mut pick = rng.random_range(0.0..total);
+        for direction in Direction::ALL {
+            let share = shares[direction.index()].max(0.0);
+            if pick < share {
+                return direction;
+            }
+            pick -= share;
+        }
+        Direction::Straight
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    #[test]
+    fn sampled_directions_follow_the_shares() {
+        let turning = TurningProportions::default()
+            .with_side(Side::FromNorth, [10.0, 75.0, 15.0])
+            .with_side(Side::FromEast, [0.0, 1.0, 3.0])
+            .with_side(Side::FromSouth, [60.0, 0.0, 40.0]);
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        let draws = 100_000;
+        for side in Side::ALL {
+            let mut counts = [0; 3];
+            for _ in 0..draws {
+                counts[turning.sample(side, &mut rng).index()] += 1;
+            }
+            for direction in Direction::ALL {
+                let share = counts[direction.index()] as f64 / draws as f64;
+                let expected = turning.proportion(side, direction);
+                assert!(
+                    (share - expected).abs() < 0.01,
+                    "{:?} {:?}: drew {:.4}, expected {:.4}",
+                    side,
+                    direction,
+                    share,
+                    expected
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use super::constants::*;
+use super::idm::*;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VehicleClass {
+    PassengerCar,
+    Van,
+    Truck,
+    ArticulatedBus,
+    Motorcycle,
+    Emergency,
+}
+
+/*
+The VehicleSpec struct holds the size and performance of a vehicle class. Sizes are in pixels, speeds in pixels per simulated second and accelerations in pixels per second squared:
+
+length: measured along the direction of travel
+width: measured across the lane, never more than a lane is wide
+max_speed: caps the desired speed of every vehicle of the class
+max_acceleration, comfortable_deceleration and min_gap: the class's car-following parameters
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct VehicleSpec {
+    pub length: i32,
+    pub width: i32,
+    pub max_speed: f64,
+    pub max_acceleration: f64,
+    pub comfortable_deceleration: f64,
+    pub min_gap: f64,
+}
+
+impl VehicleClass {
+    pub const REGULAR: [VehicleClass; 5] = [
+        VehicleClass::PassengerCar,
+        VehicleClass::Van,
+        VehicleClass::Truck,
+        VehicleClass::ArticulatedBus,
+        VehicleClass::Motorcycle,
+    ];
+
+    pub fn index(self) -> usize {
+        match self {
+            VehicleClass::PassengerCar => 0,
+            VehicleClass::Van => 1,
+            VehicleClass::Truck => 2,
+            VehicleClass::ArticulatedBus => 3,
+            VehicleClass::Motorcycle => 4,
+            VehicleClass::Emergency => 5,
+        }
+    }
+
+    /*
+    Returns the longest vehicle class, the one the intersection is laid out for.
+    */
+    pub fn longest() -> VehicleClass {
+        VehicleClass::REGULAR
+            .into_iter()
+            .chain([VehicleClass::Emergency])
+            .max_by_key(|class| class.spec().length)
+            .unwrap_or(VehicleClass::PassengerCar)
+    }
+
+    pub fn spec(self) -> VehicleSpec {
+        match self {
+            VehicleClass::PassengerCar => VehicleSpec {
+                length: CAR_WIDTH,
+                width: CAR_HEIGHT - 2,
+                max_speed: 180.0,
+                max_acceleration: IDM_MAX_ACCELERATION,
+                comfortable_deceleration: IDM_COMFORTABLE_DECELERATION,
+                min_gap: IDM_MIN_GAP,
+            },
+            VehicleClass::Van => VehicleSpec {
+                length: 26,
+                width: CAR_HEIGHT - 2,
+                max_speed: 156.0,
+                max_acceleration: 144.0,
+                comfortable_deceleration: IDM_COMFORTABLE_DECELERATION,
+                min_gap: IDM_MIN_GAP,
+            },
+            VehicleClass::Truck => VehicleSpec {
+                length: 40,
+                width: CAR_HEIGHT,
+                max_speed: 108.0,
+                max_acceleration: 90.0,
+                comfortable_deceleration: 216.0,
+                min_gap: 14.0,
+            },
+            VehicleClass::ArticulatedBus => VehicleSpec {
+                length: 56,
+                width: CAR_HEIGHT,
+                max_speed: 108.0,
+                max_acceleration: 72.0,
+                comfortable_deceleration: 216.0,
+                min_gap: 14.0,
+            },
+            VehicleClass::Motorcycle => VehicleSpec {
+                length: 12,
+                width: 8,
+                max_speed: 210.0,
+                max_acceleration: 288.0,
+                comfortable_deceleration: 360.0,
+                min_gap: 6.0,
+            },
+            VehicleClass::Emergency => VehicleSpec {
+                length: 24,
+                width: CAR_HEIGHT - 2,
+                max_speed: 210.0,
+                max_acceleration: 252.0,
+                comfortable_deceleration: IDM_COMFORTABLE_DECELERATION,
+                min_gap: IDM_MIN_GAP,
+            },
+        }
+    }
+
+    /*
+    Returns the car-following model of a vehicle of this class that would like to drive at the given speed, capped at the class's maximum speed.
+    */
+    pub fn idm(self, desired_speed: f64) -> Idm {
+        let spec = self.spec();
+        Idm {
+            desired_speed: desired_speed.min(spec.max_speed),
+            max_acceleration: spec.max_acceleration,
+            comfortable_deceleration: spec.comfortable_deceleration,
+            time_headway: IDM_TIME_HEADWAY,
+            min_gap: spec.min_gap,
+        }
+    }
+}
+
+/*
+The TrafficMix struct sets how often each vehicle class spawns. shares holds one non-negative weight per regular class, in the order of VehicleClass::REGULAR; the weights do not need to add up to one.
+Emergency vehicles are never part of the mix, they are spawned on demand.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrafficMix {
+    pub shares: [f64; 5],
+}
+
+impl Default for TrafficMix {
+    fn default() -> TrafficMix {
+        TrafficMix {
+            shares: [0.70, 0.12, 0.07, 0.03, 0.08],
+        }
+    }
+}
+
+impl TrafficMix {
+    /*
+    Returns a mix in which every vehicle is of the given regular class. Asking for emergency vehicles gives an empty mix, which spawns passenger cars.
+    */
+    pub fn only(class: VehicleClass) -> TrafficMix {
+        let mut shares = [0.0; 5];
+        if let Some(share) = shares.get_mut(class.index()) {
+            *share = 1.0;
+        }
+        TrafficMix { shares }
+    }
+
+    pub fn share(&self, class: VehicleClass) -> f64 {
+        self.shares.get(class.index()).copied().unwrap_or(0.0)
+    }
+
+    /*
+    Draws a vehicle class with probability proportional to its share. A mix without any positive share gives passenger cars.
+    */
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> VehicleClass {
+        let total: f64 = self.shares.iter().map(|share| share.max(0.0)).sum();
+        if total <= 0.0 {
+            return VehicleClass::PassengerCar;
+        }
+        let mut pick = rng.random_range(0.0..total);
+        for class in VehicleClass::REGULAR {
+            let share = self.share(class).max(0.0);
+            if pick < share {
+                return class;
+            }
+            pick -= share;
+        }
+        VehicleClass::PassengerCar
+    }
+}